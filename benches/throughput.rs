@@ -0,0 +1,97 @@
+//! Measures how long it takes to move values from one thread to another through each kind of queue.
+//!
+//! Run with `cargo bench`, then again with `cargo bench --no-default-features --features std` to
+//! see what the `cache-padding` feature is worth on the current machine.
+
+use ring_queue::{mpmc, spsc, RingQueue, TwoLockRingQueue};
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const VALUES: u64 = 1_000_000;
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<24} {:>8.1} ns/value",
+        name,
+        elapsed.as_nanos() as f64 / VALUES as f64,
+    );
+}
+
+fn ring_queue() -> Duration {
+    let queue = RingQueue::<u64, 1024>::new();
+    let started = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..VALUES {
+                queue.push(i).unwrap();
+            }
+        });
+        for _ in 0..VALUES {
+            queue.pop().unwrap();
+        }
+    });
+    started.elapsed()
+}
+
+fn two_lock() -> Duration {
+    let queue = TwoLockRingQueue::<u64, 1024>::new();
+    let started = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..VALUES {
+                queue.push(i).unwrap();
+            }
+        });
+        for _ in 0..VALUES {
+            queue.pop().unwrap();
+        }
+    });
+    started.elapsed()
+}
+
+fn spsc() -> Duration {
+    let mut queue = spsc::RingQueue::<u64, 1024>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let started = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..VALUES {
+                producer.push(i);
+            }
+        });
+        for _ in 0..VALUES {
+            consumer.pop();
+        }
+    });
+    started.elapsed()
+}
+
+fn mpmc() -> Duration {
+    let queue = mpmc::RingQueue::<u64, 1024>::new();
+    let started = Instant::now();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..VALUES {
+                queue.push(i).unwrap();
+            }
+        });
+        for _ in 0..VALUES {
+            queue.pop().unwrap();
+        }
+    });
+    started.elapsed()
+}
+
+fn main() {
+    println!(
+        "cache padding: {}",
+        if cfg!(feature = "cache-padding") { "on" } else { "off" },
+    );
+    report("RingQueue", ring_queue());
+    report("TwoLockRingQueue", two_lock());
+    report("spsc::RingQueue", spsc());
+    report("mpmc::RingQueue", mpmc());
+}
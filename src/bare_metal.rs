@@ -0,0 +1,93 @@
+//! A queue for sharing values between a main loop and interrupt handlers on targets without `std`.
+
+use crate::RingBuffer;
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+/// A concurrent fixed-size queue which protects its values with a critical section instead of a
+/// `Mutex`, so it can be shared with interrupt handlers on single-core microcontrollers. A critical
+/// section cannot be waited on, so every operation returns immediately.
+#[derive(Debug)]
+pub struct CriticalSectionRingQueue<T, const LEN: usize> {
+    ring: Mutex<RefCell<RingBuffer<T, LEN>>>,
+}
+
+impl<T, const LEN: usize> CriticalSectionRingQueue<T, LEN> {
+    /// Create a new `CriticalSectionRingQueue`. This is a `const fn`, so the queue can be put in a
+    /// `static` that the main loop and interrupt handlers share.
+    pub const fn new() -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(RingBuffer::new())),
+        }
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).len())
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.len() == LEN
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value if the
+    /// queue is full.
+    ///
+    /// This method may be called from an interrupt handler. It never allocates, never waits, and
+    /// runs in constant time regardless of `LEN`; the only work done inside the critical section is
+    /// moving `value` into the buffer. Unlike `RingQueue::try_push`, it does not wake any tasks.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        critical_section::with(|cs| self.ring.borrow_ref_mut(cs).push(value))
+    }
+
+    /// Gets the first value out of the queue if there is one. Like `try_push`, this method may be
+    /// called from an interrupt handler.
+    pub fn try_pop(&self) -> Option<T> {
+        critical_section::with(|cs| self.ring.borrow_ref_mut(cs).pop())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T, const LEN: usize> defmt::Format for CriticalSectionRingQueue<T, LEN> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        critical_section::with(|cs| {
+            defmt::write!(f, "CriticalSectionRingQueue {{ ring: {} }}", *self.ring.borrow_ref(cs))
+        });
+    }
+}
+
+impl<T, const LEN: usize> Default for CriticalSectionRingQueue<T, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_operations_do_not_block() {
+        let queue = CriticalSectionRingQueue::<u32, 2>::new();
+        assert_eq!(queue.try_pop(), None);
+        assert_eq!(queue.try_push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert!(queue.is_full());
+        assert_eq!(queue.try_push(3), Err(3));
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -0,0 +1,233 @@
+//! Adapters which move values between `RingQueue`s and other channels.
+//!
+//! The adapters for `std` and crossbeam channels each spawn a thread which forwards values until
+//! either side closes, and return its `JoinHandle`. The adapters for tokio channels are futures
+//! which do the same on whichever task they are spawned on.
+//!
+//! `crossbeam_channel::Select` only accepts crossbeam's own `Sender`s and `Receiver`s and has no
+//! public interface through which another type could register with it, so a `RingQueue` can't be
+//! added to a select set directly. Instead, forward the queue into a crossbeam channel with
+//! `spawn_to_crossbeam` and select on that channel. The forwarding thread pops each value before
+//! it sends it, so one value at a time may have left the queue without having been selected yet,
+//! even when the crossbeam channel has no capacity. If the crossbeam channel is disconnected
+//! first, that value is returned by the thread rather than being dropped.
+
+use crate::{Receiver, Sender};
+
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+/// Spawns a thread which pops values from `receiver` and sends them into `sender` until the queue
+/// is closed and empty or the `std` channel is disconnected. The thread returns the value which
+/// could not be sent because the `std` channel was disconnected, if any.
+pub fn spawn_to_mpsc<T, const LEN: usize>(
+    receiver: Receiver<T, LEN>,
+    sender: mpsc::Sender<T>,
+) -> JoinHandle<Option<T>>
+where
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        while let Ok(value) = receiver.pop() {
+            if let Err(error) = sender.send(value) {
+                return Some(error.0);
+            }
+        }
+        None
+    })
+}
+
+/// Spawns a thread which receives values from `receiver` and pushes them into `sender`, as with
+/// `Sender::push`, until the `std` channel is disconnected and empty or a value can't be pushed.
+/// The thread returns the value which could not be pushed because the queue was closed or, under
+/// `FullPolicy::Reject`, full, if any.
+pub fn spawn_from_mpsc<T, const LEN: usize>(
+    receiver: mpsc::Receiver<T>,
+    sender: Sender<T, LEN>,
+) -> JoinHandle<Option<T>>
+where
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        for value in receiver {
+            if let Err(error) = sender.push(value) {
+                return Some(error.into_inner());
+            }
+        }
+        None
+    })
+}
+
+/// Pops values from `receiver` and sends them into `sender`, waiting whenever `sender` is full,
+/// until the queue is closed and empty or the tokio channel is closed. Returns the value which
+/// could not be sent because the tokio channel was closed, if any.
+#[cfg(feature = "tokio")]
+pub async fn to_tokio<T, const LEN: usize>(
+    receiver: Receiver<T, LEN>,
+    sender: ::tokio::sync::mpsc::Sender<T>,
+) -> Option<T> {
+    while let Ok(value) = receiver.pop_async().await {
+        if let Err(error) = sender.send(value).await {
+            return Some(error.0);
+        }
+    }
+    None
+}
+
+/// Receives values from `receiver` and pushes them into `sender`, waiting whenever the queue is
+/// full, until the tokio channel is closed and empty or the queue is closed. Returns the value
+/// which could not be pushed because the queue was closed, if any.
+#[cfg(feature = "tokio")]
+pub async fn from_tokio<T, const LEN: usize>(
+    mut receiver: ::tokio::sync::mpsc::Receiver<T>,
+    sender: Sender<T, LEN>,
+) -> Option<T> {
+    while let Some(value) = receiver.recv().await {
+        if let Err(value) = sender.push_async(value).await {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Spawns a thread which pops values from `receiver` and sends them into `sender`, blocking
+/// whenever `sender` is full, until the queue is closed and empty or the crossbeam channel is
+/// disconnected. The thread returns the value which could not be sent because the crossbeam
+/// channel was disconnected, if any.
+#[cfg(feature = "crossbeam-channel")]
+pub fn spawn_to_crossbeam<T, const LEN: usize>(
+    receiver: Receiver<T, LEN>,
+    sender: crossbeam_channel::Sender<T>,
+) -> JoinHandle<Option<T>>
+where
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        while let Ok(value) = receiver.pop() {
+            if let Err(error) = sender.send(value) {
+                return Some(error.0);
+            }
+        }
+        None
+    })
+}
+
+/// Spawns a thread which receives values from `receiver` and pushes them into `sender`, as with
+/// `Sender::push`, until the crossbeam channel is disconnected and empty or a value can't be
+/// pushed. The thread returns the value which could not be pushed because the queue was closed or,
+/// under `FullPolicy::Reject`, full, if any.
+#[cfg(feature = "crossbeam-channel")]
+pub fn spawn_from_crossbeam<T, const LEN: usize>(
+    receiver: crossbeam_channel::Receiver<T>,
+    sender: Sender<T, LEN>,
+) -> JoinHandle<Option<T>>
+where
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        for value in receiver {
+            if let Err(error) = sender.push(value) {
+                return Some(error.into_inner());
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::RingQueue;
+
+    #[test]
+    fn values_round_trip_through_mpsc() {
+        let (queue_sender, queue_receiver) = RingQueue::<u32, 2>::new().split();
+        let (mpsc_sender, mpsc_receiver) = mpsc::channel();
+        let (back_sender, back_receiver) = RingQueue::<u32, 2>::new().split();
+        let there = spawn_to_mpsc(queue_receiver, mpsc_sender);
+        let back = spawn_from_mpsc(mpsc_receiver, back_sender);
+        for i in 0..10 {
+            queue_sender.push(i).unwrap();
+        }
+        queue_sender.close();
+        assert_eq!(back_receiver.iter().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(there.join().unwrap(), None);
+        assert_eq!(back.join().unwrap(), None);
+    }
+
+    #[cfg(feature = "crossbeam-channel")]
+    #[test]
+    fn ring_queues_join_crossbeam_selects() {
+        let (queue_sender, queue_receiver) = RingQueue::<u32, 2>::new().split();
+        let (crossbeam_sender, crossbeam_receiver) = crossbeam_channel::bounded(0);
+        let (other_sender, other_receiver) = crossbeam_channel::unbounded();
+        let (back_sender, back_receiver) = RingQueue::<u32, 2>::new().split();
+        let there = spawn_to_crossbeam(queue_receiver, crossbeam_sender);
+        let (merged_sender, merged_receiver) = crossbeam_channel::unbounded();
+        let back = spawn_from_crossbeam(merged_receiver, back_sender);
+        queue_sender.push(1).unwrap();
+        other_sender.send(2).unwrap();
+        queue_sender.close();
+        drop(other_sender);
+        let (mut queue_open, mut other_open) = (true, true);
+        while queue_open || other_open {
+            let mut select = crossbeam_channel::Select::new();
+            let queue_index = queue_open.then(|| select.recv(&crossbeam_receiver));
+            let other_index = other_open.then(|| select.recv(&other_receiver));
+            let operation = select.select();
+            let (receiver, open) = if Some(operation.index()) == queue_index {
+                (&crossbeam_receiver, &mut queue_open)
+            } else {
+                assert_eq!(Some(operation.index()), other_index);
+                (&other_receiver, &mut other_open)
+            };
+            match operation.recv(receiver) {
+                Ok(value) => merged_sender.send(value).unwrap(),
+                Err(_) => *open = false,
+            }
+        }
+        drop(merged_sender);
+        let mut received = back_receiver.iter().collect::<Vec<_>>();
+        received.sort_unstable();
+        assert_eq!(there.join().unwrap(), None);
+        assert_eq!(back.join().unwrap(), None);
+        assert_eq!(received, vec![1, 2]);
+    }
+
+    #[cfg(feature = "crossbeam-channel")]
+    #[test]
+    fn forwarded_value_is_returned_once_crossbeam_disconnects() {
+        let (queue_sender, queue_receiver) = RingQueue::<u32, 2>::new().split();
+        let (crossbeam_sender, crossbeam_receiver) = crossbeam_channel::bounded(0);
+        let there = spawn_to_crossbeam(queue_receiver, crossbeam_sender);
+        queue_sender.push(1).unwrap();
+        drop(crossbeam_receiver);
+        assert_eq!(there.join().unwrap(), Some(1));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn values_round_trip_through_tokio() {
+        let (queue_sender, queue_receiver) = RingQueue::<u32, 2>::new().split();
+        let (tokio_sender, tokio_receiver) = ::tokio::sync::mpsc::channel(2);
+        let (back_sender, back_receiver) = RingQueue::<u32, 2>::new().split();
+        let there = tokio::spawn(to_tokio(queue_receiver, tokio_sender));
+        let back = tokio::spawn(from_tokio(tokio_receiver, back_sender));
+        let producer = tokio::spawn(async move {
+            for i in 0..10 {
+                queue_sender.push_async(i).await.unwrap();
+            }
+        });
+        let mut received = vec![];
+        while let Ok(value) = back_receiver.pop_async().await {
+            received.push(value);
+        }
+        producer.await.unwrap();
+        assert_eq!(there.await.unwrap(), None);
+        assert_eq!(back.await.unwrap(), None);
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,405 @@
+//! A queue whose every subscriber sees every value, for fanning one stream of values out to many
+//! independent consumers.
+//!
+//! Each `Subscriber` has its own read cursor. A value stays in the queue until every subscriber
+//! has popped it, so the queue is full when its slowest subscriber is `LEN` values behind. What
+//! `push` does then depends on the queue's `FullPolicy`. With `FullPolicy::DropOldest`, the
+//! subscribers which have not yet popped the oldest value skip past it.
+
+use crate::{
+    sync::{Condvar, Mutex, MutexGuard},
+    Closed, FullPolicy, PopError, PopTimeoutError, PushError,
+};
+
+use std::{collections::BTreeMap, time::Duration};
+
+/// A concurrent fixed-size queue which hands a clone of every value pushed into it to every
+/// `Subscriber`. Values pushed while there are no subscribers are dropped.
+#[derive(Debug)]
+pub struct RingQueue<T, const LEN: usize> {
+    // All the stuff that needs to be synchronized.
+    shared: Mutex<Shared<T, LEN>>,
+    // The condition to wait on in `Subscriber::pop`.
+    pop_cond: Condvar,
+    // The condition to wait on in `push`.
+    push_cond: Condvar,
+    // What `push` does when the queue is full.
+    full_policy: FullPolicy,
+}
+
+#[derive(Debug)]
+struct Shared<T, const LEN: usize> {
+    // The values which some subscriber has yet to pop. The value with counter `n` is in
+    // `values[n % LEN]` and is `Some` exactly when `n` is in `head..tail`.
+    values: [Option<T>; LEN],
+    // The counter of the oldest value which some subscriber has yet to pop.
+    head: u64,
+    // The counter of the next value to be pushed.
+    tail: u64,
+    // The counter of the next value that each subscriber will pop, by subscriber ID. No cursor is
+    // ever before `head` or after `tail`.
+    cursors: BTreeMap<u64, u64>,
+    // The ID to give the next subscriber.
+    next_id: u64,
+    // Whether any more values may be pushed into the queue.
+    closed: bool,
+}
+
+impl<T, const LEN: usize> Shared<T, LEN> {
+    fn index(counter: u64) -> usize {
+        // Reducing the counter before narrowing it keeps the index correct on targets where
+        // `usize` is narrower than `u64`.
+        (counter % LEN as u64) as usize
+    }
+
+    fn is_full(&self) -> bool {
+        self.tail.wrapping_sub(self.head) == LEN as u64
+    }
+
+    fn subscribe_at(&mut self, cursor: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.cursors.insert(id, cursor);
+        id
+    }
+
+    // Removes every value which each subscriber has already popped, returning them so that they
+    // can be dropped once the queue is unlocked.
+    fn release(&mut self) -> Vec<T> {
+        let head = self.cursors.values().copied().min().unwrap_or(self.tail);
+        let mut released = vec![];
+        while self.head != head {
+            released.extend(self.values[Self::index(self.head)].take());
+            self.head = self.head.wrapping_add(1);
+        }
+        released
+    }
+
+    // Removes the oldest value, moving every subscriber which has yet to pop it past it.
+    fn evict(&mut self) -> Option<T> {
+        let evicted = self.values[Self::index(self.head)].take();
+        self.head = self.head.wrapping_add(1);
+        for cursor in self.cursors.values_mut() {
+            if *cursor == self.head.wrapping_sub(1) {
+                *cursor = self.head;
+            }
+        }
+        evicted
+    }
+}
+
+impl<T, const LEN: usize> RingQueue<T, LEN> {
+    /// Create a new `broadcast::RingQueue` whose `push` blocks while the slowest subscriber is
+    /// `LEN` values behind.
+    pub fn new() -> Self {
+        Self::with_full_policy(FullPolicy::Block)
+    }
+
+    /// Create a new `broadcast::RingQueue` whose `push` follows `full_policy` while the slowest
+    /// subscriber is `LEN` values behind.
+    pub fn with_full_policy(full_policy: FullPolicy) -> Self {
+        Self {
+            shared: Mutex::new(Shared {
+                values: [const { None }; LEN],
+                head: 0,
+                tail: 0,
+                cursors: BTreeMap::new(),
+                next_id: 0,
+                closed: false,
+            }),
+            pop_cond: Condvar::new(),
+            push_cond: Condvar::new(),
+            full_policy,
+        }
+    }
+
+    /// The number of values which some subscriber has yet to pop.
+    pub fn len(&self) -> usize {
+        let shared = self.shared.lock();
+        shared.tail.wrapping_sub(shared.head) as usize
+    }
+
+    /// Whether every subscriber has popped every value.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of values that the slowest subscriber can fall behind by.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// The number of live subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.shared.lock().cursors.len()
+    }
+
+    /// Creates a new `Subscriber`, which will see every value pushed from now on.
+    pub fn subscribe(&self) -> Subscriber<'_, T, LEN> {
+        let mut shared = self.shared.lock();
+        let tail = shared.tail;
+        let id = shared.subscribe_at(tail);
+        Subscriber { queue: self, id }
+    }
+
+    /// Adds a new value to the end of the queue for every subscriber to pop. If the slowest
+    /// subscriber is `LEN` values behind, what happens depends on the queue's `FullPolicy`. Returns
+    /// the value in `PushError::Closed` if the queue is closed, or in `PushError::Full` if it is
+    /// full and the policy is `FullPolicy::Reject`.
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        span!("broadcast_push");
+        let mut shared = match self.full_policy {
+            FullPolicy::Block => self.push_cond.wait_while(self.shared.lock(), |shared| {
+                shared.is_full() && !shared.closed
+            }),
+            _ => self.shared.lock(),
+        };
+        if shared.closed {
+            return Err(PushError::Closed(value));
+        }
+        if shared.cursors.is_empty() {
+            drop(shared);
+            trace!("Dropped value pushed into queue without subscribers");
+            return Ok(());
+        }
+        let mut evicted = None;
+        if shared.is_full() {
+            match self.full_policy {
+                FullPolicy::Block => unreachable!("waited for room in the queue"),
+                // A queue which can't hold any values has no oldest value to drop instead.
+                FullPolicy::DropOldest if LEN > 0 => {
+                    evicted = shared.evict();
+                    trace!("Dropped oldest value from full queue");
+                }
+                FullPolicy::DropNewest | FullPolicy::DropOldest => {
+                    drop(shared);
+                    trace!("Dropped value pushed into full queue");
+                    return Ok(());
+                }
+                FullPolicy::Reject => return Err(PushError::Full(value)),
+            }
+        }
+        let index = Shared::<T, LEN>::index(shared.tail);
+        shared.values[index] = Some(value);
+        shared.tail = shared.tail.wrapping_add(1);
+        trace!("Pushed value into queue, {} queued", shared.tail.wrapping_sub(shared.head));
+        self.pop_cond.notify_all();
+        drop(shared);
+        drop(evicted);
+        Ok(())
+    }
+
+    /// Closes the queue. Subscribers can still pop the values that are already in the queue, but
+    /// every attempt to push a new value will fail and every thread blocked in `push` or
+    /// `Subscriber::pop` is woken.
+    pub fn close(&self) {
+        self.shared.lock().closed = true;
+        self.pop_cond.notify_all();
+        self.push_cond.notify_all();
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+}
+
+impl<T, const LEN: usize> Default for RingQueue<T, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reader of a `broadcast::RingQueue` with its own cursor. Created by `RingQueue::subscribe`.
+#[derive(Debug)]
+pub struct Subscriber<'a, T, const LEN: usize> {
+    queue: &'a RingQueue<T, LEN>,
+    // The key of this subscriber's cursor in the queue's `cursors`.
+    id: u64,
+}
+
+impl<T, const LEN: usize> Subscriber<'_, T, LEN> {
+    /// The number of values that this subscriber has yet to pop.
+    pub fn len(&self) -> usize {
+        let shared = self.queue.shared.lock();
+        shared.tail.wrapping_sub(shared.cursors[&self.id]) as usize
+    }
+
+    /// Whether this subscriber has popped every value in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const LEN: usize> Subscriber<'_, T, LEN>
+where
+    T: Clone,
+{
+    /// Gets the next value that this subscriber has yet to pop. Blocks while there is no such
+    /// value and the queue is open. Returns `Err(Closed)` once the queue has been closed and this
+    /// subscriber has popped every value in it.
+    pub fn pop(&mut self) -> Result<T, Closed> {
+        let shared = self.queue.shared.lock();
+        let shared = self.queue.pop_cond.wait_while(shared, |shared| {
+            shared.cursors[&self.id] == shared.tail && !shared.closed
+        });
+        self.pop_locked(shared).ok_or(Closed)
+    }
+
+    /// Gets the next value that this subscriber has yet to pop if there is one. Returns
+    /// `PopError::Empty` immediately if there is no such value, or `PopError::Closed` if the queue
+    /// is also closed.
+    pub fn try_pop(&mut self) -> Result<T, PopError> {
+        let shared = self.queue.shared.lock();
+        let closed = shared.closed;
+        self.pop_locked(shared).ok_or(if closed {
+            PopError::Closed
+        } else {
+            PopError::Empty
+        })
+    }
+
+    /// Gets the next value that this subscriber has yet to pop. Blocks while there is no such
+    /// value and the queue is open, but for no longer than `timeout`. Returns
+    /// `PopTimeoutError::Timeout` if there was still no value when the timeout elapsed, or
+    /// `PopTimeoutError::Closed` if the queue was closed.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        let shared = self.queue.shared.lock();
+        let (shared, _) = self.queue.pop_cond.wait_timeout_while(shared, timeout, |shared| {
+            shared.cursors[&self.id] == shared.tail && !shared.closed
+        });
+        let closed = shared.closed;
+        self.pop_locked(shared).ok_or(if closed {
+            PopTimeoutError::Closed
+        } else {
+            PopTimeoutError::Timeout
+        })
+    }
+
+    // Pops the next value for this subscriber if there is one. The value is moved out of the queue
+    // instead of cloned if no other subscriber has yet to pop it.
+    fn pop_locked(&self, mut shared: MutexGuard<'_, Shared<T, LEN>>) -> Option<T> {
+        let cursor = shared.cursors[&self.id];
+        if cursor == shared.tail {
+            return None;
+        }
+        shared.cursors.insert(self.id, cursor.wrapping_add(1));
+        let index = Shared::<T, LEN>::index(cursor);
+        // No cursor is ever before `head`, so if no other subscriber is still at `cursor`, this
+        // subscriber was the last one that had yet to pop the value.
+        let last = cursor == shared.head && shared.cursors.values().all(|&other| other != cursor);
+        if last {
+            shared.head = shared.head.wrapping_add(1);
+            self.queue.push_cond.notify_all();
+            shared.values[index].take()
+        } else {
+            shared.values[index].clone()
+        }
+    }
+}
+
+impl<T, const LEN: usize> Clone for Subscriber<'_, T, LEN> {
+    /// Creates a new subscriber which has yet to pop the same values as this one.
+    fn clone(&self) -> Self {
+        let mut shared = self.queue.shared.lock();
+        let cursor = shared.cursors[&self.id];
+        let id = shared.subscribe_at(cursor);
+        Self {
+            queue: self.queue,
+            id,
+        }
+    }
+}
+
+impl<T, const LEN: usize> Drop for Subscriber<'_, T, LEN> {
+    fn drop(&mut self) {
+        let mut shared = self.queue.shared.lock();
+        shared.cursors.remove(&self.id);
+        let released = shared.release();
+        if !released.is_empty() {
+            self.queue.push_cond.notify_all();
+        }
+        drop(shared);
+        drop(released);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn every_subscriber_sees_every_value() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.push(0), Ok(()));
+        let mut subscribers = [queue.subscribe(), queue.subscribe(), queue.subscribe()];
+        thread::scope(|scope| {
+            let readers = subscribers
+                .iter_mut()
+                .map(|subscriber| {
+                    scope.spawn(move || {
+                        let mut ret = vec![];
+                        while let Ok(value) = subscriber.pop() {
+                            ret.push(value);
+                        }
+                        ret
+                    })
+                })
+                .collect::<Vec<_>>();
+            for i in 1..=10 {
+                queue.push(i).unwrap();
+            }
+            queue.close();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), (1..=10).collect::<Vec<_>>());
+            }
+        });
+        assert!(queue.is_empty());
+        assert_eq!(queue.push(11), Err(PushError::Closed(11)));
+    }
+
+    #[test]
+    fn slowest_subscriber_governs_full_queue() {
+        let queue = RingQueue::<u32, 2>::with_full_policy(FullPolicy::Reject);
+        let mut fast = queue.subscribe();
+        let mut slow = queue.subscribe();
+        queue.push(0).unwrap();
+        queue.push(1).unwrap();
+        assert_eq!(fast.try_pop(), Ok(0));
+        assert_eq!(fast.try_pop(), Ok(1));
+        assert_eq!(queue.push(2), Err(PushError::Full(2)));
+        assert_eq!(slow.try_pop(), Ok(0));
+        assert_eq!(queue.push(2), Ok(()));
+        let mut late = slow.clone();
+        drop(slow);
+        assert_eq!(late.try_pop(), Ok(1));
+        assert_eq!(late.try_pop(), Ok(2));
+        assert_eq!(late.try_pop(), Err(PopError::Empty));
+        assert_eq!(fast.try_pop(), Ok(2));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn overwriting_skips_lagging_subscribers_ahead() {
+        let queue = RingQueue::<u32, 2>::with_full_policy(FullPolicy::DropOldest);
+        let mut fast = queue.subscribe();
+        let mut slow = queue.subscribe();
+        for i in 0..2 {
+            queue.push(i).unwrap();
+            assert_eq!(fast.try_pop(), Ok(i));
+        }
+        for i in 2..5 {
+            queue.push(i).unwrap();
+            assert_eq!(fast.try_pop(), Ok(i));
+        }
+        assert_eq!(slow.len(), 2);
+        assert_eq!(slow.try_pop(), Ok(3));
+        assert_eq!(slow.try_pop(), Ok(4));
+        assert_eq!(
+            slow.pop_timeout(Duration::from_millis(1)),
+            Err(PopTimeoutError::Timeout)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Construction-time configuration for `RingQueue`.
+
+use crate::{
+    future::Wakers,
+    padding::CachePadded,
+    sync::{DefaultRawMutex, RawCondvar},
+    BoxedRingQueue, Inner, RingQueue, Storage,
+};
+
+use std::{
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::{atomic::AtomicU32, Arc},
+};
+
+use lock_api::{Mutex, RawMutex};
+
+/// What `RingQueue::push` does when the queue is full. Values discarded by a policy are passed to
+/// the queue's eviction callback, if it has one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FullPolicy {
+    /// Block until there is room for the new value. This is the default.
+    #[default]
+    Block,
+    /// Discard the new value and report success.
+    DropNewest,
+    /// Discard the first value in the queue to make room for the new value.
+    DropOldest,
+    /// Hand the new value back to the caller.
+    Reject,
+}
+
+/// How a thread waits in `RingQueue::push` and `RingQueue::pop` while the queue is full or empty.
+/// Strategies which park less wake up sooner but burn more CPU time while waiting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WaitStrategy {
+    /// Check again in a tight loop until the wait is over. Never sleeps, so it keeps a core busy
+    /// for as long as it waits.
+    BusySpin,
+    /// Yield to the scheduler between checks. Never sleeps, but lets other threads on the same
+    /// core run.
+    Yield,
+    /// Check again in a tight loop up to the given number of times, then sleep until woken.
+    SpinThenPark(u32),
+    /// Like `SpinThenPark`, but the queue picks the number of times to check from how long its
+    /// recent waits took. Spins for longer while waits are short and stops spinning once they are
+    /// usually longer than it would be willing to spin.
+    Adaptive,
+    /// Sleep until woken. This is the default.
+    #[default]
+    Park,
+}
+
+/// Configures and creates a `RingQueue` which is locked by an `M`. Created by
+/// `RingQueue::builder`.
+#[derive(Debug)]
+pub struct Builder<T, const LEN: usize, M = DefaultRawMutex> {
+    full_policy: FullPolicy,
+    on_evict: Option<EvictCallback<T>>,
+    spsc: bool,
+    wait_strategy: WaitStrategy,
+    _values: PhantomData<fn() -> T>,
+    _mutex: PhantomData<fn() -> M>,
+}
+
+impl<T, const LEN: usize> Builder<T, LEN> {
+    /// Create a new `Builder` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, const LEN: usize, M> Builder<T, LEN, M>
+where
+    M: RawMutex,
+{
+    /// Makes the queue lock an `R` instead of an `M`, such as an RTOS mutex or a
+    /// priority-inheritance mutex. Threads that block in the queue still sleep with `std` while
+    /// they wait for the queue to change, but only ever wait for the queue's lock through `R`.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 4>::builder().raw_mutex::<parking_lot::RawMutex>().build();
+    /// queue.push(1).unwrap();
+    /// assert_eq!(queue.pop(), Ok(1));
+    /// ```
+    pub fn raw_mutex<R>(self) -> Builder<T, LEN, R>
+    where
+        R: RawMutex,
+    {
+        Builder {
+            full_policy: self.full_policy,
+            on_evict: self.on_evict,
+            spsc: self.spsc,
+            wait_strategy: self.wait_strategy,
+            _values: PhantomData,
+            _mutex: PhantomData,
+        }
+    }
+
+    /// Sets what `push` does when the queue is full.
+    pub fn full_policy(mut self, full_policy: FullPolicy) -> Self {
+        self.full_policy = full_policy;
+        self
+    }
+
+    /// Sets a callback which is given every value that the queue's `FullPolicy` discards.
+    pub fn on_evict<F>(mut self, on_evict: F) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        self.on_evict = Some(EvictCallback(Arc::new(on_evict)));
+        self
+    }
+
+    /// Optimizes the queue for use by exactly one async producer and one async consumer by keeping
+    /// a single slot for the waker of each side instead of a list of them. Only the most recent
+    /// task to wait on each side will be woken, so at most one task may wait to push and one task
+    /// may wait to pop at a time. Threads blocked in `push` and `pop` are unaffected.
+    pub fn spsc(mut self) -> Self {
+        self.spsc = true;
+        self
+    }
+
+    /// Makes `push` and `pop` check whether they can continue up to `iterations` more times before
+    /// putting the thread to sleep while the queue is full or empty. When waits are usually short,
+    /// this saves most of the cost of sleeping and being woken at the price of some CPU time. The
+    /// default is not to spin at all. Shorthand for `wait_strategy(WaitStrategy::SpinThenPark(_))`.
+    pub fn spin(self, iterations: u32) -> Self {
+        self.wait_strategy(WaitStrategy::SpinThenPark(iterations))
+    }
+
+    /// Sets how `push` and `pop` wait while the queue is full or empty.
+    pub fn wait_strategy(mut self, wait_strategy: WaitStrategy) -> Self {
+        self.wait_strategy = wait_strategy;
+        self
+    }
+
+    /// Creates an empty `RingQueue` with this configuration.
+    pub fn build(self) -> RingQueue<T, LEN, [MaybeUninit<T>; LEN], M> {
+        self.build_in([const { MaybeUninit::uninit() }; LEN])
+    }
+
+    /// Creates an empty `RingQueue` with this configuration which keeps its values on the heap.
+    /// The values are never placed on the stack, even temporarily.
+    pub fn build_boxed(self) -> BoxedRingQueue<T, LEN, M> {
+        let values = (0..LEN).map(|_| MaybeUninit::uninit()).collect::<Box<[_]>>();
+        self.build_in(values.try_into().expect("collected exactly `LEN` values"))
+    }
+
+    // Creates an empty `RingQueue` with this configuration which keeps its values in `storage`.
+    pub(crate) fn build_in<S>(self, storage: S) -> RingQueue<T, LEN, S, M>
+    where
+        S: Storage<T>,
+    {
+        let mut inner = Inner::new(storage);
+        if self.spsc {
+            inner.pop_wakers = Wakers::One(None);
+            inner.push_wakers = Wakers::One(None);
+        }
+        RingQueue {
+            inner: Mutex::new(inner),
+            pop_cond: CachePadded(RawCondvar::new()),
+            push_cond: CachePadded(RawCondvar::new()),
+            full_policy: self.full_policy,
+            on_evict: self.on_evict,
+            wait_strategy: self.wait_strategy,
+            spin_estimate: AtomicU32::new(0),
+        }
+    }
+}
+
+impl<T, const LEN: usize> Default for Builder<T, LEN> {
+    fn default() -> Self {
+        Self {
+            full_policy: FullPolicy::default(),
+            on_evict: None,
+            spsc: false,
+            wait_strategy: WaitStrategy::default(),
+            _values: PhantomData,
+            _mutex: PhantomData,
+        }
+    }
+}
+
+// A user-supplied function which consumes values discarded by a `FullPolicy`.
+pub(crate) struct EvictCallback<T>(pub(crate) Arc<dyn Fn(T) + Send + Sync>);
+
+impl<T> Clone for EvictCallback<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Debug for EvictCallback<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("EvictCallback")
+    }
+}
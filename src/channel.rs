@@ -0,0 +1,562 @@
+//! Owned producer and consumer handles to a shared `RingQueue`.
+
+use crate::{
+    Closed, DefaultRawMutex, Iter, PopError, PopFuture, PopTimeoutError, PushError, PushFuture,
+    PushTimeoutError, RingQueue, Storage, TryIter, TryPushError,
+};
+
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+#[cfg(feature = "futures")]
+use futures_sink::Sink;
+use lock_api::RawMutex;
+
+#[cfg(feature = "futures")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// The state shared by every handle to a split queue.
+#[derive(Debug)]
+struct Shared<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: RingQueue<T, LEN, S, M>,
+    // The number of live `Sender`s. The queue is closed when this reaches 0.
+    senders: AtomicUsize,
+    // The number of live `Receiver`s. The queue is closed when this reaches 0.
+    receivers: AtomicUsize,
+}
+
+/// The producing half of a `RingQueue` that has been split by `RingQueue::split`.
+#[derive(Debug)]
+pub struct Sender<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    shared: Arc<Shared<T, LEN, S, M>>,
+    // The value which has been given to `Sink::start_send` but not yet pushed into the queue.
+    #[cfg(feature = "futures")]
+    pending: Option<T>,
+}
+
+impl<T, const LEN: usize, S, M> Sender<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn new(shared: Arc<Shared<T, LEN, S, M>>) -> Self {
+        Self {
+            shared,
+            #[cfg(feature = "futures")]
+            pending: None,
+        }
+    }
+
+    /// Adds a new value to the end of the queue. If the queue is full, what happens depends on the
+    /// queue's `FullPolicy`, as with `RingQueue::push`. Returns the value in
+    /// `SendError::Disconnected` if every `Receiver` has been dropped, in `SendError::Closed` if
+    /// the queue was closed some other way, or in `SendError::Full` if the queue is full and the
+    /// policy is `FullPolicy::Reject`.
+    pub fn push(&self, value: T) -> Result<(), SendError<T>> {
+        self.shared.queue.push(value).map_err(|error| match error {
+            PushError::Full(value) => SendError::Full(value),
+            PushError::Closed(value) if self.shared.receivers.load(Ordering::Acquire) == 0 => {
+                SendError::Disconnected(value)
+            }
+            PushError::Closed(value) => SendError::Closed(value),
+        })
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value
+    /// immediately if the queue is full or closed. See `RingQueue::try_push`.
+    pub fn try_push(&self, value: T) -> Result<(), TryPushError<T>> {
+        self.shared.queue.try_push(value)
+    }
+
+    /// Adds a new value to the end of the queue. Blocks while the queue is full, but for no longer
+    /// than `timeout`. Returns the value if the queue was still full when the timeout elapsed or
+    /// the queue is closed. See `RingQueue::push_timeout`.
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        self.shared.queue.push_timeout(value, timeout)
+    }
+
+    /// Adds a new value to the end of the queue. Blocks while the queue is full, but not past
+    /// `deadline`. Returns the value if the queue was still full when the deadline passed or the
+    /// queue is closed. See `RingQueue::push_until`.
+    pub fn push_until(&self, value: T, deadline: Instant) -> Result<(), PushTimeoutError<T>> {
+        self.shared.queue.push_until(value, deadline)
+    }
+
+    /// Adds a new value to the end of the queue without blocking a thread. See
+    /// `RingQueue::push_async`.
+    pub fn push_async(&self, value: T) -> PushFuture<'_, T, LEN, S, M> {
+        self.shared.queue.push_async(value)
+    }
+
+    /// Closes the queue. See `RingQueue::close`.
+    pub fn close(&self) {
+        self.shared.queue.close()
+    }
+
+    /// Whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+}
+
+impl<T, const LEN: usize, S, M> Clone for Sender<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self::new(Arc::clone(&self.shared))
+    }
+}
+
+impl<T, const LEN: usize, S, M> Drop for Sender<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+/// The consuming half of a `RingQueue` that has been split by `RingQueue::split`.
+#[derive(Debug)]
+pub struct Receiver<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    shared: Arc<Shared<T, LEN, S, M>>,
+}
+
+impl<T, const LEN: usize, S, M> Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and every value in it has been popped.
+    pub fn pop(&self) -> Result<T, Closed> {
+        self.shared.queue.pop()
+    }
+
+    /// Gets the first value out of the queue if there is one. Returns immediately if the queue is
+    /// empty. See `RingQueue::try_pop`.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.shared.queue.try_pop()
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but for no
+    /// longer than `timeout`. See `RingQueue::pop_timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.shared.queue.pop_timeout(timeout)
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but not
+    /// past `deadline`. See `RingQueue::pop_until`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.shared.queue.pop_until(deadline)
+    }
+
+    /// Gets the first value out of the queue without blocking a thread. See
+    /// `RingQueue::pop_async`.
+    pub fn pop_async(&self) -> PopFuture<'_, T, LEN, S, M> {
+        self.shared.queue.pop_async()
+    }
+
+    /// Returns an iterator which pops values out of the queue, blocking while it is empty. The
+    /// iterator ends once the queue has been closed and every value in it has been popped.
+    pub fn iter(&self) -> Iter<'_, T, LEN, S, M> {
+        self.shared.queue.iter()
+    }
+
+    /// Returns an iterator which pops the values that are currently in the queue. The iterator
+    /// ends as soon as the queue is empty instead of blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T, LEN, S, M> {
+        self.shared.queue.try_iter()
+    }
+
+    /// Closes the queue. See `RingQueue::close`.
+    pub fn close(&self) {
+        self.shared.queue.close()
+    }
+
+    /// Whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+}
+
+impl<T, const LEN: usize, S, M> Clone for Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T, const LEN: usize, S, M> Drop for Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+/// Pops values out of the queue, blocking while it is empty, as with `Receiver::iter`. Ends once
+/// the queue has been closed, such as by every `Sender` being dropped, and every value in it has
+/// been popped.
+///
+/// `Receiver` itself is not an `Iterator`, since its `next` would clash with `StreamExt::next`
+/// when the `futures` feature implements `Stream` for it.
+impl<T, const LEN: usize, S, M> IntoIterator for Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+    type IntoIter = ReceiverIntoIter<T, LEN, S, M>;
+
+    fn into_iter(self) -> ReceiverIntoIter<T, LEN, S, M> {
+        ReceiverIntoIter { receiver: self }
+    }
+}
+
+/// Pops values out of the queue, blocking while it is empty, as with `Receiver::iter`. Ends once
+/// the queue has been closed, such as by every `Sender` being dropped, and every value in it has
+/// been popped.
+impl<'a, T, const LEN: usize, S, M> IntoIterator for &'a Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+    type IntoIter = Iter<'a, T, LEN, S, M>;
+
+    fn into_iter(self) -> Iter<'a, T, LEN, S, M> {
+        self.iter()
+    }
+}
+
+/// An iterator which owns a `Receiver` and pops values out of its queue, blocking while it is
+/// empty. Created by `Receiver`'s `IntoIterator` implementation.
+#[derive(Debug)]
+pub struct ReceiverIntoIter<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    receiver: Receiver<T, LEN, S, M>,
+}
+
+impl<T, const LEN: usize, S, M> Iterator for ReceiverIntoIter<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.pop().ok()
+    }
+}
+
+/// Pushes values into the queue, waiting while it is full. The sink is only ready once the queue
+/// has room, so it never holds a value that the queue couldn't take. A value which could not be
+/// pushed because the queue was closed is handed back in the error. Closing the sink only flushes
+/// it; the queue is closed once every `Sender` has been dropped.
+#[cfg(feature = "futures")]
+impl<T, const LEN: usize, S, M> Sink<T> for Sender<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Error = Disconnected<T>;
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        match self.as_mut().poll_flush(cx) {
+            Poll::Ready(Ok(())) => self.shared.queue.poll_room(cx).map(Ok),
+            poll => poll,
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        assert!(this.pending.is_none(), "`start_send` called without `poll_ready`");
+        this.pending = Some(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        if this.pending.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+        this.shared.queue.poll_push(cx, &mut this.pending).map_err(Disconnected)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+// `Sender` never pins its pending value, so it may move that value even while it is pinned.
+#[cfg(feature = "futures")]
+impl<T, const LEN: usize, S, M> Unpin for Sender<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{}
+
+/// Yields the values popped from the queue, ending once the queue has been closed and every value in
+/// it has been popped.
+#[cfg(feature = "futures")]
+impl<T, const LEN: usize, S, M> Stream for Receiver<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.shared.queue.poll_pop(cx).map(Result::ok)
+    }
+}
+
+/// The error returned by `Sender::push`. Contains the value that could not be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SendError<T> {
+    /// The queue is full and its `FullPolicy` is `FullPolicy::Reject`.
+    Full(T),
+    /// The queue was closed while some `Receiver` was still alive.
+    Closed(T),
+    /// Every `Receiver` has been dropped, so no value will ever be popped again.
+    Disconnected(T),
+}
+
+impl<T> SendError<T> {
+    /// The value that could not be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) | Self::Disconnected(value) => value,
+        }
+    }
+
+    /// Whether the value could not be pushed because the queue is full.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Whether the value could not be pushed because the queue was closed while some `Receiver`
+    /// was still alive.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+
+    /// Whether the value could not be pushed because every `Receiver` has been dropped.
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Self::Disconnected(_))
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "queue is full"),
+            Self::Closed(_) => write!(f, "queue is closed"),
+            Self::Disconnected(_) => write!(f, "queue is disconnected"),
+        }
+    }
+}
+
+impl<T> Error for SendError<T> where T: Debug {}
+
+/// The error returned by `Sender`'s `Sink` implementation when no `Receiver` will ever see the
+/// value. Contains the value that could not be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Disconnected<T>(pub T);
+
+impl<T> Display for Disconnected<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "queue is disconnected")
+    }
+}
+
+impl<T> Error for Disconnected<T>
+where
+    T: Debug,
+{
+}
+
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Splits the queue into a `Sender` and a `Receiver` which share ownership of it. Both halves
+    /// can be cloned and sent to other threads. The queue is closed once every `Sender` or every
+    /// `Receiver` has been dropped.
+    pub fn split(self) -> (Sender<T, LEN, S, M>, Receiver<T, LEN, S, M>) {
+        let shared = Arc::new(Shared {
+            queue: self,
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+        });
+        (Sender::new(Arc::clone(&shared)), Receiver { shared })
+    }
+}
+
+/// Creates a new `RingQueue` which can hold `LEN` values and splits it into a `Sender` and a
+/// `Receiver`. Equivalent to `RingQueue::new().split()`.
+///
+/// ```
+/// use std::thread;
+///
+/// let (sender, receiver) = ring_queue::channel::<u32, 4>();
+/// let producer = thread::spawn(move || {
+///     for i in 0..10 {
+///         sender.push(i).unwrap();
+///     }
+/// });
+/// assert_eq!(receiver.iter().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+/// producer.join().unwrap();
+/// ```
+pub fn channel<T, const LEN: usize>() -> (Sender<T, LEN>, Receiver<T, LEN>) {
+    RingQueue::new().split()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::FullPolicy;
+
+    use std::thread;
+
+    #[test]
+    fn split_halves_work_across_threads() {
+        let (sender, receiver) = RingQueue::<u32, 4>::new().split();
+        let senders = (0..2)
+            .map(|n| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in (n * 10)..(n * 10 + 10) {
+                        sender.push(i).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let consumer = thread::spawn(move || {
+            let mut ret = vec![];
+            while let Ok(value) = receiver.pop() {
+                ret.push(value);
+            }
+            ret
+        });
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        drop(sender);
+        let mut received = consumer.join().unwrap();
+        received.sort_unstable();
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn receiver_iterates_until_every_sender_is_dropped() {
+        let (sender, receiver) = RingQueue::<u32, 2>::new().split();
+        let producer = thread::spawn(move || {
+            for i in 0..5 {
+                sender.push(i).unwrap();
+            }
+        });
+        let mut received = vec![];
+        for value in &receiver {
+            received.push(value);
+        }
+        producer.join().unwrap();
+        assert_eq!(received, (0..5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn receiver_can_be_moved_into_a_for_loop() {
+        let (sender, receiver) = RingQueue::<u32, 2>::new().split();
+        let consumer = thread::spawn(move || {
+            let mut received = vec![];
+            for value in receiver {
+                received.push(value);
+            }
+            received
+        });
+        for i in 0..5 {
+            sender.push(i).unwrap();
+        }
+        drop(sender);
+        assert_eq!(consumer.join().unwrap(), (0..5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn push_fails_once_every_receiver_is_dropped() {
+        let (sender, receiver) = RingQueue::<u32, 1>::new().split();
+        sender.push(3).unwrap();
+        let blocked = {
+            let sender = sender.clone();
+            thread::spawn(move || sender.push(4))
+        };
+        drop(receiver);
+        assert_eq!(blocked.join().unwrap(), Err(SendError::Disconnected(4)));
+        assert_eq!(sender.push(5), Err(SendError::Disconnected(5)));
+    }
+
+    #[test]
+    fn push_tells_closing_apart_from_disconnection() {
+        let (sender, _receiver) = RingQueue::<u32, 1>::new().split();
+        sender.close();
+        assert_eq!(sender.push(3), Err(SendError::Closed(3)));
+    }
+
+    #[test]
+    fn push_reports_full_queue_as_full() {
+        let (sender, _receiver) =
+            RingQueue::<u32, 1>::builder().full_policy(FullPolicy::Reject).build().split();
+        sender.push(3).unwrap();
+        assert_eq!(sender.push(4), Err(SendError::Full(4)));
+    }
+}
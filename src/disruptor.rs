@@ -0,0 +1,485 @@
+//! A ring buffer whose values are processed in place by a pipeline of consumers, in the style of
+//! the LMAX Disruptor.
+//!
+//! Every `Stage` sees every value, in order. A stage may depend on other stages, in which case it
+//! only sees a value once every stage it depends on has finished with it. A stage claims every
+//! value which is available to it as one contiguous `Batch`, so a stage which falls behind catches
+//! up in large batches instead of one value at a time. A value is dropped once every stage has
+//! finished with it, and the producer waits for the slowest stage whenever the ring is full.
+//!
+//! ```
+//! use ring_queue::disruptor::Disruptor;
+//! use std::thread;
+//!
+//! let disruptor = Disruptor::<u32, 16>::new();
+//! let mut journal = disruptor.stage(&[]);
+//! let mut replicate = disruptor.stage(&[]);
+//! let mut logic = disruptor.stage(&[journal.id(), replicate.id()]);
+//! thread::scope(|scope| {
+//!     scope.spawn(move || while let Ok(_batch) = journal.claim() { /* write to disk */ });
+//!     scope.spawn(move || while let Ok(_batch) = replicate.claim() { /* send to replica */ });
+//!     let logic = scope.spawn(move || {
+//!         let mut sum = 0;
+//!         while let Ok(batch) = logic.claim() {
+//!             sum += batch.iter().sum::<u32>();
+//!         }
+//!         sum
+//!     });
+//!     for i in 0..100 {
+//!         disruptor.publish(i).unwrap();
+//!     }
+//!     disruptor.close();
+//!     assert_eq!(logic.join().unwrap(), (0..100).sum());
+//! });
+//! ```
+
+use crate::{
+    sync::{Condvar, Mutex, MutexGuard},
+    Closed,
+};
+
+use std::{cell::UnsafeCell, mem::MaybeUninit, ptr};
+
+/// A fixed-size ring buffer whose values are processed in place by a pipeline of `Stage`s.
+#[derive(Debug)]
+pub struct Disruptor<T, const LEN: usize> {
+    // The values in the ring. It is an invariant that the value with sequence number `n` is in
+    // `values[n % LEN]` and that it is initialized exactly when `n` is at least the sequence of
+    // the slowest stage and less than the cursor.
+    values: [UnsafeCell<MaybeUninit<T>>; LEN],
+    // The sequences of the producer and every stage.
+    state: Mutex<State>,
+    // The condition to wait on for any sequence to move. Waiters are always woken all at once
+    // because they wait for many different sequences.
+    cond: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    // The sequence number which the next published value will have.
+    cursor: u64,
+    // Every stage that has been created, indexed by `StageId`. A stage is `None` once dropped.
+    stages: Vec<Option<StageState>>,
+    // Whether any more values may be published.
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct StageState {
+    // The sequence number of the next value that the stage will process.
+    sequence: u64,
+    // The stages which must finish with a value before this stage may process it.
+    upstream: Vec<StageId>,
+}
+
+impl State {
+    // The sequence number of the oldest value which some stage has yet to finish with.
+    fn min_sequence(&self) -> u64 {
+        self.stages
+            .iter()
+            .flatten()
+            .map(|stage| stage.sequence)
+            .min()
+            .unwrap_or(self.cursor)
+    }
+
+    // The sequence number one past the last value which the stage `id` may process. A stage which
+    // has been dropped no longer holds its dependents back.
+    fn available(&self, id: StageId) -> u64 {
+        self.stages[id.index]
+            .as_ref()
+            .expect("a live stage")
+            .upstream
+            .iter()
+            .filter_map(|upstream| self.stages[upstream.index].as_ref())
+            .map(|upstream| upstream.sequence)
+            .min()
+            .unwrap_or(self.cursor)
+    }
+
+    fn is_full(&self, len: usize) -> bool {
+        self.cursor - self.min_sequence() == len as u64
+    }
+}
+
+impl<T, const LEN: usize> Disruptor<T, LEN> {
+    /// Create a new `Disruptor` which has no stages.
+    pub fn new() -> Self {
+        Self {
+            values: [const { UnsafeCell::new(MaybeUninit::uninit()) }; LEN],
+            state: Mutex::new(State {
+                cursor: 0,
+                stages: vec![],
+                closed: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// The maximum number of values that the slowest stage can fall behind by.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Creates a new `Stage` which only processes a value once every stage in `upstream` has
+    /// finished with it. The stage starts at the slowest stage in `upstream`, or at the next value
+    /// to be published if `upstream` is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of `upstream` belongs to a different `Disruptor`.
+    pub fn stage(&self, upstream: &[StageId]) -> Stage<'_, T, LEN> {
+        assert!(
+            upstream.iter().all(|id| id.disruptor == self.address()),
+            "upstream stage belongs to a different `Disruptor`"
+        );
+        let mut state = self.state.lock();
+        let sequence = upstream
+            .iter()
+            .filter_map(|upstream| state.stages[upstream.index].as_ref())
+            .map(|upstream| upstream.sequence)
+            .min()
+            .unwrap_or(state.cursor);
+        let id = StageId {
+            disruptor: self.address(),
+            index: state.stages.len(),
+        };
+        state.stages.push(Some(StageState {
+            sequence,
+            upstream: upstream.to_vec(),
+        }));
+        Stage {
+            disruptor: self,
+            id,
+        }
+    }
+
+    /// Adds a new value to the end of the ring for every stage to process. Blocks while the ring is
+    /// full. Returns the value if the disruptor is closed. A value published while there are no
+    /// stages is dropped immediately.
+    pub fn publish(&self, value: T) -> Result<(), T> {
+        let state = self.state.lock();
+        let state = self.cond.wait_while(state, |state| state.is_full(LEN) && !state.closed);
+        self.publish_locked(state, value)
+    }
+
+    /// Adds a new value to the end of the ring for every stage to process if there is room for it.
+    /// Returns the value if the ring is full or the disruptor is closed.
+    pub fn try_publish(&self, value: T) -> Result<(), T> {
+        let state = self.state.lock();
+        if state.is_full(LEN) {
+            return Err(value);
+        }
+        self.publish_locked(state, value)
+    }
+
+    /// Closes the disruptor. Stages can still process the values that have already been
+    /// published, but every attempt to publish a new value will fail and every thread blocked in
+    /// `publish` is woken.
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.cond.notify_all();
+    }
+
+    /// Whether `close` has been called on this disruptor.
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().closed
+    }
+
+    // Identifies this disruptor for as long as it has stages, which keep it from moving.
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn publish_locked(&self, mut state: MutexGuard<'_, State>, value: T) -> Result<(), T> {
+        if state.closed {
+            return Err(value);
+        }
+        if state.stages.iter().all(Option::is_none) {
+            return Ok(());
+        }
+        let index = (state.cursor % LEN as u64) as usize;
+        // SAFETY: This write is safe because the ring isn't full, so the value with this sequence
+        //         number's slot isn't initialized and no stage can be reading from it.
+        unsafe { (*self.values[index].get()).write(value) };
+        state.cursor += 1;
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    // Drops every value whose sequence number is in `start..end`.
+    //
+    // SAFETY: Calling this function is only safe while `state` is locked, when every stage's
+    //         sequence was at least `start` and now is at least `end`.
+    unsafe fn release(&self, start: u64, end: u64) {
+        for sequence in start..end {
+            let index = (sequence % LEN as u64) as usize;
+            ptr::drop_in_place((*self.values[index].get()).as_mut_ptr());
+        }
+    }
+}
+
+impl<T, const LEN: usize> Default for Disruptor<T, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const LEN: usize> Drop for Disruptor<T, LEN> {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        let (start, end) = (state.min_sequence(), state.cursor);
+        // SAFETY: This call is safe because nothing else can access the disruptor while it is
+        //         being dropped, and the values in `start..end` are exactly the ones which the
+        //         invariant on `self.values` guarantees to be initialized.
+        unsafe { self.release(start, end) };
+    }
+}
+
+// SAFETY: This impl is safe because every value is only written by `publish` while no stage can
+//         read it, and is only dropped once every stage has finished with it. Several stages may
+//         read the same value at once, and a value published by one thread may be processed and
+//         dropped by others, so values must be both `Send` and `Sync`.
+unsafe impl<T, const LEN: usize> Sync for Disruptor<T, LEN> where T: Send + Sync {}
+
+/// Identifies a `Stage` so that other stages may depend on it. Created by `Stage::id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StageId {
+    // The address of the `Disruptor` which the stage belongs to.
+    disruptor: usize,
+    // The index of the stage in the disruptor's `stages`.
+    index: usize,
+}
+
+/// A consumer of the values in a `Disruptor`. Created by `Disruptor::stage`.
+///
+/// Dropping a stage stops it from holding back the producer and the stages which depend on it.
+#[derive(Debug)]
+pub struct Stage<'a, T, const LEN: usize> {
+    disruptor: &'a Disruptor<T, LEN>,
+    id: StageId,
+}
+
+impl<'a, T, const LEN: usize> Stage<'a, T, LEN> {
+    /// The identifier which other stages can use to depend on this one.
+    pub fn id(&self) -> StageId {
+        self.id
+    }
+
+    /// Claims every value which this stage may process. Blocks while there are no such values and
+    /// the disruptor is open. Returns `Err(Closed)` once the disruptor has been closed and this
+    /// stage has processed every value that was published.
+    pub fn claim(&mut self) -> Result<Batch<'_, 'a, T, LEN>, Closed> {
+        let state = self.disruptor.state.lock();
+        let state = self.disruptor.cond.wait_while(state, |state| {
+            state.available(self.id) == self.sequence(state)
+                && !(state.closed && self.sequence(state) == state.cursor)
+        });
+        let (start, end) = (self.sequence(&state), state.available(self.id));
+        drop(state);
+        if start == end {
+            return Err(Closed);
+        }
+        Ok(Batch {
+            stage: self,
+            start,
+            end,
+        })
+    }
+
+    /// Claims every value which this stage may process without blocking. Returns `None` if there
+    /// are no such values.
+    pub fn try_claim(&mut self) -> Option<Batch<'_, 'a, T, LEN>> {
+        let state = self.disruptor.state.lock();
+        let (start, end) = (self.sequence(&state), state.available(self.id));
+        drop(state);
+        if start == end {
+            return None;
+        }
+        Some(Batch {
+            stage: self,
+            start,
+            end,
+        })
+    }
+
+    fn sequence(&self, state: &State) -> u64 {
+        state.stages[self.id.index].as_ref().expect("a live stage").sequence
+    }
+
+    // Moves this stage past every value before `end`, dropping any values which every stage has
+    // now finished with.
+    fn advance(&self, end: u64, remove: bool) {
+        let mut state = self.disruptor.state.lock();
+        let start = state.min_sequence();
+        if remove {
+            state.stages[self.id.index] = None;
+        } else if let Some(stage) = &mut state.stages[self.id.index] {
+            stage.sequence = end;
+        }
+        // SAFETY: This call is safe because `state` is locked and `start` was the slowest stage's
+        //         sequence, which no stage has moved back from.
+        unsafe { self.disruptor.release(start, state.min_sequence()) };
+        self.disruptor.cond.notify_all();
+    }
+}
+
+impl<T, const LEN: usize> Drop for Stage<'_, T, LEN> {
+    fn drop(&mut self) {
+        self.advance(0, true);
+    }
+}
+
+/// A contiguous run of values which a `Stage` has claimed. The stage finishes with the values when
+/// the batch is dropped.
+#[derive(Debug)]
+pub struct Batch<'s, 'a, T, const LEN: usize> {
+    stage: &'s Stage<'a, T, LEN>,
+    // The sequence number of the first value in the batch.
+    start: u64,
+    // The sequence number one past the last value in the batch.
+    end: u64,
+}
+
+impl<T, const LEN: usize> Batch<'_, '_, T, LEN> {
+    /// The number of values in the batch.
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    /// Whether the batch contains no values. A claimed batch always contains at least one.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The sequence number of the first value in the batch. Values are numbered from 0 in the
+    /// order that they were published.
+    pub fn sequence(&self) -> u64 {
+        self.start
+    }
+
+    /// Gets the value at `index` in the batch, or `None` if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let slot = ((self.start + index as u64) % LEN as u64) as usize;
+        // SAFETY: This dereference is safe because the value has been published and, while this
+        //         batch exists, its stage's sequence keeps the value from being released.
+        Some(unsafe { (*self.stage.disruptor.values[slot].get()).assume_init_ref() })
+    }
+
+    /// Returns an iterator over the values in the batch, in the order that they were published.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+}
+
+impl<T, const LEN: usize> Drop for Batch<'_, '_, T, LEN> {
+    fn drop(&mut self) {
+        self.stage.advance(self.end, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        mem,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    #[test]
+    fn downstream_stages_wait_for_upstream_stages() {
+        let disruptor = Disruptor::<u32, 4>::new();
+        let mut journal = disruptor.stage(&[]);
+        let mut replicate = disruptor.stage(&[]);
+        let mut logic = disruptor.stage(&[journal.id(), replicate.id()]);
+        for i in 0..3 {
+            disruptor.publish(i).unwrap();
+        }
+        assert!(logic.try_claim().is_none());
+        assert_eq!(journal.try_claim().unwrap().iter().copied().collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(logic.try_claim().is_none());
+        let batch = replicate.try_claim().unwrap();
+        assert_eq!((batch.sequence(), batch.len()), (0, 3));
+        drop(batch);
+        disruptor.publish(3).unwrap();
+        assert_eq!(disruptor.try_publish(4), Err(4));
+        let batch = logic.try_claim().unwrap();
+        assert_eq!(batch.iter().copied().collect::<Vec<_>>(), [0, 1, 2]);
+        drop(batch);
+        assert_eq!(disruptor.try_publish(4), Ok(()));
+        disruptor.close();
+        drop(replicate);
+        assert_eq!(journal.claim().unwrap().len(), 2);
+        assert_eq!(logic.claim().unwrap().get(1), Some(&4));
+        assert!(logic.claim().is_err());
+    }
+
+    #[test]
+    fn values_are_dropped_once_every_stage_has_finished() {
+        #[derive(Debug)]
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let disruptor = Disruptor::<DropCounter, 4>::new();
+        disruptor.publish(DropCounter(Arc::clone(&drops))).unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        let mut first = disruptor.stage(&[]);
+        let second = disruptor.stage(&[first.id()]);
+        for _ in 0..3 {
+            disruptor.publish(DropCounter(Arc::clone(&drops))).unwrap();
+        }
+        drop(first.claim().unwrap());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        drop(second);
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+        disruptor.publish(DropCounter(Arc::clone(&drops))).unwrap();
+        // Values which a leaked stage never finished with are dropped with the disruptor.
+        mem::forget(first);
+        drop(disruptor);
+        assert_eq!(drops.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn pipeline_processes_every_value_in_order() {
+        let disruptor = Disruptor::<u32, 8>::new();
+        let stages = vec![disruptor.stage(&[]), disruptor.stage(&[])];
+        let ids = [stages[0].id(), stages[1].id()];
+        let logic = disruptor.stage(&ids);
+        thread::scope(|scope| {
+            let consumers = stages
+                .into_iter()
+                .chain([logic])
+                .map(|mut stage| {
+                    scope.spawn(move || {
+                        let mut ret = vec![];
+                        while let Ok(batch) = stage.claim() {
+                            ret.extend(batch.iter().copied());
+                        }
+                        ret
+                    })
+                })
+                .collect::<Vec<_>>();
+            for i in 0..1000 {
+                disruptor.publish(i).unwrap();
+            }
+            disruptor.close();
+            for consumer in consumers {
+                assert_eq!(consumer.join().unwrap(), (0..1000).collect::<Vec<_>>());
+            }
+        });
+    }
+}
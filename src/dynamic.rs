@@ -0,0 +1,222 @@
+//! A queue whose capacity is chosen at runtime.
+
+use crate::{
+    Builder, Closed, Iter, PopBatchFuture, PopError, PopFuture, PopTimeoutError, PushError,
+    PushFuture, PushTimeoutError, RingQueue, TryIter, TryPushError,
+};
+
+use std::{
+    mem::MaybeUninit,
+    time::{Duration, Instant},
+};
+
+// The storage of a `DynRingQueue`.
+type Values<T> = Box<[MaybeUninit<T>]>;
+
+/// A concurrent queue whose capacity is chosen when it is created instead of at compile time, for
+/// example from a configuration file. It otherwise behaves exactly like a `RingQueue` with the
+/// default `FullPolicy`.
+#[derive(Debug)]
+pub struct DynRingQueue<T> {
+    // The `LEN` of this queue is meaningless. Every operation used here gets the capacity from the
+    // storage instead.
+    queue: RingQueue<T, 0, Values<T>>,
+}
+
+impl<T> DynRingQueue<T> {
+    /// Create a new `DynRingQueue` which can hold `capacity` values.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let values = (0..capacity).map(|_| MaybeUninit::uninit()).collect();
+        Self {
+            queue: Builder::new().build_in(values),
+        }
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether the queue currently contains as many values as it can hold.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.queue.inner.lock().capacity()
+    }
+
+    /// Gets the first value out of the queue. See `RingQueue::pop`.
+    pub fn pop(&self) -> Result<T, Closed> {
+        self.queue.pop()
+    }
+
+    /// Gets the first `n` values out of the queue, in order. See `RingQueue::pop_many`.
+    pub fn pop_many(&self, n: usize) -> Vec<T> {
+        self.queue.pop_many(n)
+    }
+
+    /// Gets up to `n` values out of the queue, in order, without blocking. See
+    /// `RingQueue::pop_up_to`.
+    pub fn pop_up_to(&self, n: usize) -> Vec<T> {
+        self.queue.pop_up_to(n)
+    }
+
+    /// Gets the first value out of the queue if there is one. See `RingQueue::try_pop`.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.queue.try_pop()
+    }
+
+    /// Gets the first value out of the queue, waiting for no longer than `timeout`. See
+    /// `RingQueue::pop_timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.queue.pop_timeout(timeout)
+    }
+
+    /// Gets the first value out of the queue, waiting no later than `deadline`. See
+    /// `RingQueue::pop_until`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.queue.pop_until(deadline)
+    }
+
+    /// Adds a new value to the end of the queue, blocking while the queue is full. See
+    /// `RingQueue::push`.
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        self.queue.push(value)
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch. See
+    /// `RingQueue::push_all`.
+    pub fn push_all<I>(&self, values: I) -> Result<(), Vec<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.queue.push_all(values)
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch if there is
+    /// room for all of them. See `RingQueue::try_push_all`.
+    pub fn try_push_all<I>(&self, values: I) -> Result<(), Vec<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.queue.try_push_all(values)
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. See
+    /// `RingQueue::try_push`.
+    pub fn try_push(&self, value: T) -> Result<(), TryPushError<T>> {
+        self.queue.try_push(value)
+    }
+
+    /// Adds a new value to the end of the queue, evicting the first value if the queue is full.
+    /// See `RingQueue::force_push`.
+    pub fn force_push(&self, value: T) -> Option<T> {
+        self.queue.force_push(value)
+    }
+
+    /// Adds a new value to the end of the queue, waiting for no longer than `timeout`. See
+    /// `RingQueue::push_timeout`.
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        self.queue.push_timeout(value, timeout)
+    }
+
+    /// Adds a new value to the end of the queue, waiting no later than `deadline`. See
+    /// `RingQueue::push_until`.
+    pub fn push_until(&self, value: T, deadline: Instant) -> Result<(), PushTimeoutError<T>> {
+        self.queue.push_until(value, deadline)
+    }
+
+    /// Adds a new value to the end of the queue without blocking a thread. See
+    /// `RingQueue::push_async`.
+    pub fn push_async(&self, value: T) -> PushFuture<'_, T, 0, Values<T>> {
+        self.queue.push_async(value)
+    }
+
+    /// Gets the first value out of the queue without blocking a thread. See
+    /// `RingQueue::pop_async`.
+    pub fn pop_async(&self) -> PopFuture<'_, T, 0, Values<T>> {
+        self.queue.pop_async()
+    }
+
+    /// Gets up to `n` values out of the queue without blocking a thread. See
+    /// `RingQueue::pop_batch_async`.
+    pub fn pop_batch_async(&self, n: usize) -> PopBatchFuture<'_, T, 0, Values<T>> {
+        self.queue.pop_batch_async(n)
+    }
+
+    /// Removes every value that is currently in the queue, in order, without blocking.
+    pub fn drain(&self) -> Vec<T> {
+        self.queue.drain()
+    }
+
+    /// Gets a copy of the first value in the queue without removing it. Returns `None` if the
+    /// queue is empty.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.queue.peek()
+    }
+
+    /// Calls `f` on the first value in the queue without removing it. See `RingQueue::peek_with`.
+    pub fn peek_with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.queue.peek_with(f)
+    }
+
+    /// Returns an iterator which pops values out of the queue, blocking while it is empty. See
+    /// `RingQueue::iter`.
+    pub fn iter(&self) -> Iter<'_, T, 0, Values<T>> {
+        self.queue.iter()
+    }
+
+    /// Returns an iterator which pops the values that are currently in the queue. See
+    /// `RingQueue::try_iter`.
+    pub fn try_iter(&self) -> TryIter<'_, T, 0, Values<T>> {
+        self.queue.try_iter()
+    }
+
+    /// Closes the queue. See `RingQueue::close`.
+    pub fn close(&self) {
+        self.queue.close()
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn capacity_is_chosen_at_runtime() {
+        let capacity = "3".parse().unwrap();
+        let queue = Arc::new(DynRingQueue::with_capacity(capacity));
+        assert_eq!(queue.capacity(), 3);
+        assert_eq!(queue.try_push_all(0..3), Ok(()));
+        assert!(queue.is_full());
+        assert_eq!(queue.try_push(3), Err(TryPushError::Full(3)));
+        let pusher = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || queue.push_all(3..5))
+        };
+        assert_eq!(queue.pop_many(5), vec![0, 1, 2, 3, 4]);
+        assert_eq!(pusher.join().unwrap(), Ok(()));
+        assert_eq!(queue.force_push(5), None);
+        assert_eq!(queue.drain(), vec![5]);
+    }
+}
@@ -0,0 +1,174 @@
+//! The errors returned by a `RingQueue`'s non-blocking and timed operations. Every error from an
+//! operation that was given a value contains that value, so a failed push never drops it.
+
+use core::fmt::{self, Display, Formatter};
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// The error returned by `RingQueue::push`. Contains the value that could not be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PushError<T> {
+    /// The queue is full and its `FullPolicy` is `FullPolicy::Reject`.
+    Full(T),
+    /// The queue is closed.
+    Closed(T),
+}
+
+impl<T> PushError<T> {
+    /// The value that could not be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
+
+    /// Whether the value could not be pushed because the queue is full.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Whether the value could not be pushed because the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+}
+
+impl<T> Display for PushError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "queue is full"),
+            Self::Closed(_) => write!(f, "queue is closed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Error for PushError<T> where T: fmt::Debug {}
+
+/// The error returned by `RingQueue::try_push`. Contains the value that could not be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TryPushError<T> {
+    /// The queue is full.
+    Full(T),
+    /// The queue is closed.
+    Closed(T),
+}
+
+impl<T> TryPushError<T> {
+    /// The value that could not be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Full(value) | Self::Closed(value) => value,
+        }
+    }
+
+    /// Whether the value could not be pushed because the queue is full.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// Whether the value could not be pushed because the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+}
+
+impl<T> Display for TryPushError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "queue is full"),
+            Self::Closed(_) => write!(f, "queue is closed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Error for TryPushError<T> where T: fmt::Debug {}
+
+/// The error returned by `RingQueue::push_timeout` and `RingQueue::push_until`. Contains the value
+/// that could not be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PushTimeoutError<T> {
+    /// The queue was still full when the time ran out.
+    Timeout(T),
+    /// The queue is closed.
+    Closed(T),
+}
+
+impl<T> PushTimeoutError<T> {
+    /// The value that could not be pushed.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Timeout(value) | Self::Closed(value) => value,
+        }
+    }
+
+    /// Whether the value could not be pushed because the time ran out.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout(_))
+    }
+
+    /// Whether the value could not be pushed because the queue is closed.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed(_))
+    }
+}
+
+impl<T> Display for PushTimeoutError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout(_) => write!(f, "timed out waiting for room in queue"),
+            Self::Closed(_) => write!(f, "queue is closed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Error for PushTimeoutError<T> where T: fmt::Debug {}
+
+/// The error returned by `RingQueue::try_pop`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PopError {
+    /// The queue is empty, but more values may be pushed into it.
+    Empty,
+    /// The queue is closed and empty, so no more values will ever be popped from it.
+    Closed,
+}
+
+impl Display for PopError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "queue is empty"),
+            Self::Closed => write!(f, "queue is closed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for PopError {}
+
+/// The error returned by `RingQueue::pop_timeout` and `RingQueue::pop_until`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PopTimeoutError {
+    /// The queue was still empty when the time ran out.
+    Timeout,
+    /// The queue is closed and empty, so no more values will ever be popped from it.
+    Closed,
+}
+
+impl Display for PopTimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timed out waiting for a value in queue"),
+            Self::Closed => write!(f, "queue is closed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for PopTimeoutError {}
@@ -0,0 +1,337 @@
+//! A version of `RingQueue` which never panics, for use where unwinding out of a call is not
+//! acceptable, such as across an FFI boundary or in a driver.
+//!
+//! Every operation which can fail returns a `Result`, including construction and cloning, and
+//! every panic raised by user code that the queue calls -- `Clone` implementations, eviction
+//! callbacks, and `Waker`s -- is caught and reported as `Error::Panicked`. The queue is still
+//! usable after such a panic. Operations which only report on the queue's state, like `len`, can't
+//! fail and return their results directly. A caught panic's payload is leaked rather than dropped,
+//! since dropping it could panic in turn.
+//!
+//! Dropping a queue is the one exception: `Drop` can't return an error, so a panic from the `Drop`
+//! implementation of a value still in the queue unwinds out of the drop as usual. Tear a queue down
+//! with `RingQueue::try_drop` instead to have such panics reported.
+//!
+//! Panics can only be caught when the crate is built with `panic = "unwind"`. With
+//! `panic = "abort"` a panic in user code still aborts the process.
+
+use crate::{
+    Builder, Closed, DefaultRawMutex, PopError, PopTimeoutError, PushError, PushTimeoutError,
+    Storage, TryPushError,
+};
+
+use lock_api::RawMutex;
+
+use std::{
+    convert::{Infallible, TryInto},
+    error,
+    fmt::{self, Debug, Display, Formatter},
+    mem::{self, MaybeUninit},
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+/// The error returned by the operations in this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The operation failed in the same way as the `crate::RingQueue` method of the same name.
+    Queue(E),
+    /// The memory for the queue could not be allocated.
+    OutOfMemory,
+    /// User code called by the operation panicked.
+    Panicked,
+}
+
+impl<E> Display for Error<E>
+where
+    E: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Queue(error) => error.fmt(f),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::Panicked => write!(f, "user code panicked"),
+        }
+    }
+}
+
+impl<E> error::Error for Error<E> where E: Debug + Display {}
+
+/// A `RingQueue` which keeps its values on the heap. Created by `RingQueue::new_boxed`.
+pub type BoxedRingQueue<T, const LEN: usize> = RingQueue<T, LEN, Box<[MaybeUninit<T>; LEN]>>;
+
+/// A `crate::RingQueue` whose operations never panic.
+#[derive(Debug)]
+pub struct RingQueue<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: crate::RingQueue<T, LEN, S, M>,
+}
+
+impl<T, const LEN: usize> RingQueue<T, LEN> {
+    /// Create a new `RingQueue`. Creating a queue which keeps its values inside itself can't
+    /// fail, but this still returns a `Result` so that every constructor has the same shape.
+    pub fn new() -> Result<Self, Error<Infallible>> {
+        Ok(crate::RingQueue::new().into())
+    }
+
+    /// Create a new `RingQueue` which keeps its values on the heap. Returns
+    /// `Error::OutOfMemory` instead of aborting if there is not enough memory for them.
+    pub fn new_boxed() -> Result<BoxedRingQueue<T, LEN>, Error<Infallible>> {
+        let mut values = Vec::new();
+        values.try_reserve_exact(LEN).map_err(|_| Error::OutOfMemory)?;
+        values.resize_with(LEN, MaybeUninit::uninit);
+        // Converting a boxed slice of exactly `LEN` values into a boxed array can't fail.
+        let values = values.into_boxed_slice().try_into().map_err(|_| Error::OutOfMemory)?;
+        let queue: crate::BoxedRingQueue<T, LEN> = Builder::new().build_in(values);
+        Ok(queue.into())
+    }
+
+    /// Creates a new `RingQueue` containing a clone of every value in this queue. Returns
+    /// `Error::Panicked` if cloning one of the values panicked.
+    pub fn try_clone(&self) -> Result<Self, Error<Infallible>>
+    where
+        T: Clone,
+    {
+        catch(|| Ok(self.queue.clone().into()))
+    }
+}
+
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Gets the first value out of the queue. See `crate::RingQueue::pop`.
+    pub fn pop(&self) -> Result<T, Error<Closed>> {
+        catch(|| self.queue.pop())
+    }
+
+    /// Gets the first value out of the queue if there is one. See `crate::RingQueue::try_pop`.
+    pub fn try_pop(&self) -> Result<T, Error<PopError>> {
+        catch(|| self.queue.try_pop())
+    }
+
+    /// Gets the first value out of the queue, waiting for no longer than `timeout`. See
+    /// `crate::RingQueue::pop_timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, Error<PopTimeoutError>> {
+        catch(|| self.queue.pop_timeout(timeout))
+    }
+
+    /// Gets the first value out of the queue, waiting no later than `deadline`. See
+    /// `crate::RingQueue::pop_until`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, Error<PopTimeoutError>> {
+        catch(|| self.queue.pop_until(deadline))
+    }
+
+    /// Adds a new value to the end of the queue. See `crate::RingQueue::push`. If the queue's
+    /// eviction callback panics, the value that it was given is lost.
+    pub fn push(&self, value: T) -> Result<(), Error<PushError<T>>> {
+        catch(|| self.queue.push(value))
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch. See
+    /// `crate::RingQueue::push_all`. Instead of panicking when there are more values than the
+    /// queue's capacity, hands them all back.
+    pub fn push_all(&self, values: Vec<T>) -> Result<(), Error<Vec<T>>> {
+        if values.len() > LEN {
+            return Err(Error::Queue(values));
+        }
+        catch(|| self.queue.push_all(values))
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. See
+    /// `crate::RingQueue::try_push`.
+    pub fn try_push(&self, value: T) -> Result<(), Error<TryPushError<T>>> {
+        catch(|| self.queue.try_push(value))
+    }
+
+    /// Adds a new value to the end of the queue, waiting for no longer than `timeout`. See
+    /// `crate::RingQueue::push_timeout`.
+    pub fn push_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), Error<PushTimeoutError<T>>> {
+        catch(|| self.queue.push_timeout(value, timeout))
+    }
+
+    /// Adds a new value to the end of the queue, waiting no later than `deadline`. See
+    /// `crate::RingQueue::push_until`.
+    pub fn push_until(
+        &self,
+        value: T,
+        deadline: Instant,
+    ) -> Result<(), Error<PushTimeoutError<T>>> {
+        catch(|| self.queue.push_until(value, deadline))
+    }
+
+    /// Closes the queue. See `crate::RingQueue::close`.
+    pub fn close(&self) -> Result<(), Error<Infallible>> {
+        catch(|| {
+            self.queue.close();
+            Ok(())
+        })
+    }
+
+    /// Whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    /// Drops every value left in the queue and then the queue itself, reporting a panic from any
+    /// of their `Drop` implementations as `Error::Panicked` instead of unwinding. The remaining
+    /// values are still dropped after one of them panics.
+    pub fn try_drop(self) -> Result<(), Error<Infallible>> {
+        let mut ret = Ok(());
+        loop {
+            match catch(|| self.queue.try_pop().map(drop)) {
+                Ok(()) => {}
+                Err(Error::Queue(_)) => break,
+                Err(_) => ret = Err(Error::Panicked),
+            }
+        }
+        catch(move || {
+            drop(self);
+            Ok(())
+        })?;
+        ret
+    }
+
+    /// Gets the queue that this wraps, whose operations may panic.
+    pub fn into_inner(self) -> crate::RingQueue<T, LEN, S, M> {
+        self.queue
+    }
+}
+
+impl<T, const LEN: usize, S, M> From<crate::RingQueue<T, LEN, S, M>> for RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn from(queue: crate::RingQueue<T, LEN, S, M>) -> Self {
+        Self { queue }
+    }
+}
+
+// Runs `f`, turning a panic into `Error::Panicked`. Asserting unwind safety is sound as far as the
+// queue goes, because its locks are never poisoned and its state is consistent whenever user code
+// runs (see `sync`). The payload is forgotten, since its own `Drop` implementation might panic
+// outside of `catch_unwind`.
+fn catch<R, E, F>(f: F) -> Result<R, Error<E>>
+where
+    F: FnOnce() -> Result<R, E>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(Error::Queue),
+        Err(payload) => {
+            mem::forget(payload);
+            Err(Error::Panicked)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, rc::Rc};
+
+    // Panics when it is dropped.
+    #[derive(Debug)]
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("cannot drop");
+        }
+    }
+
+    #[test]
+    fn panics_are_reported_as_errors() {
+        #[derive(Debug)]
+        struct PanicOnClone;
+
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                panic!("cannot clone");
+            }
+        }
+
+        let queue = RingQueue::<PanicOnClone, 2>::new().unwrap();
+        queue.push(PanicOnClone).unwrap();
+        assert_eq!(queue.try_clone().err(), Some(Error::Panicked));
+        assert!(queue.try_pop().is_ok());
+        assert_eq!(queue.try_pop().err(), Some(Error::Queue(PopError::Empty)));
+    }
+
+    #[test]
+    fn oversized_batches_are_handed_back() {
+        let queue = RingQueue::<u32, 2>::new_boxed().unwrap();
+        assert_eq!(queue.push_all(vec![1, 2, 3]), Err(Error::Queue(vec![1, 2, 3])));
+        assert_eq!(queue.push_all(vec![1, 2]), Ok(()));
+        queue.close().unwrap();
+        assert_eq!(queue.push(3), Err(Error::Queue(PushError::Closed(3))));
+        assert_eq!(queue.pop(), Ok(1));
+    }
+
+    #[test]
+    fn panicking_payloads_are_not_dropped() {
+        #[derive(Debug)]
+        struct PanicWithPayload;
+
+        impl Clone for PanicWithPayload {
+            fn clone(&self) -> Self {
+                panic::panic_any(PanicOnDrop);
+            }
+        }
+
+        let queue = RingQueue::<PanicWithPayload, 2>::new().unwrap();
+        queue.push(PanicWithPayload).unwrap();
+        assert_eq!(queue.try_clone().err(), Some(Error::Panicked));
+    }
+
+    #[test]
+    fn try_drop_reports_panicking_values() {
+        // Counts how many times it has been dropped.
+        #[derive(Debug)]
+        struct DropCounter(Rc<Cell<usize>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let queue = RingQueue::<Result<DropCounter, PanicOnDrop>, 3>::new().unwrap();
+        queue.push(Ok(DropCounter(Rc::clone(&drops)))).unwrap();
+        queue.push(Err(PanicOnDrop)).unwrap();
+        queue.push(Ok(DropCounter(Rc::clone(&drops)))).unwrap();
+        assert_eq!(queue.try_drop(), Err(Error::Panicked));
+        assert_eq!(drops.get(), 2);
+        assert_eq!(RingQueue::<u32, 2>::new().unwrap().try_drop(), Ok(()));
+    }
+}
@@ -0,0 +1,569 @@
+//! Futures which wait for room in or values from a `RingQueue` without blocking a thread.
+
+use crate::{Closed, DefaultRawMutex, RingQueue, Storage};
+
+use lock_api::RawMutex;
+
+use std::{
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// A future which adds a value to the end of a `RingQueue` once there is room for it. Created by
+/// `RingQueue::push_async`.
+///
+/// # Cancellation
+///
+/// The value is only moved into the queue when the future completes with `Ok(())`. Until then, it
+/// is held by the future and can be recovered with `PushFuture::into_inner`, so a future which
+/// loses a race (e.g. in `select!`) can give its value back instead of dropping it.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PushFuture<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    // The value to push. Only `None` once the future has completed.
+    value: Option<T>,
+}
+
+impl<T, const LEN: usize, S, M> PushFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Gives back the value if it has not been pushed yet. Returns `None` if the future has
+    /// already completed.
+    pub fn into_inner(self) -> Option<T> {
+        self.value
+    }
+}
+
+impl<T, const LEN: usize, S, M> Future for PushFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Output = Result<(), T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.queue.poll_push(cx, &mut this.value)
+    }
+}
+
+// `PushFuture` never pins the value it holds, so it may move that value even while it is pinned.
+impl<T, const LEN: usize, S, M> Unpin for PushFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{}
+
+/// A future which gets the first value out of a `RingQueue` once there is one. Created by
+/// `RingQueue::pop_async`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PopFuture<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+}
+
+impl<T, const LEN: usize, S, M> Future for PopFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Output = Result<T, Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.queue.poll_pop(cx)
+    }
+}
+
+/// A future which gets a batch of values out of a `RingQueue` once there is at least one. Created
+/// by `RingQueue::pop_batch_async`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PopBatchFuture<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    // The most values to pop.
+    n: usize,
+}
+
+impl<T, const LEN: usize, S, M> Future for PopBatchFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Output = Result<Vec<T>, Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.n == 0 {
+            return Poll::Ready(Ok(vec![]));
+        }
+        let mut inner = self.queue.inner.lock();
+        if inner.len() == 0 {
+            if inner.closed {
+                return Poll::Ready(Err(Closed));
+            }
+            inner.pop_wakers.register(cx.waker());
+            return Poll::Pending;
+        }
+        let mut ret = Vec::with_capacity(self.n.min(inner.len()));
+        while ret.len() < self.n && inner.len() > 0 {
+            ret.push(inner.pop());
+        }
+        trace!("Popped {} values from queue, {} remaining", ret.len(), inner.len());
+        self.queue.notify_popped(&mut inner);
+        Poll::Ready(Ok(ret))
+    }
+}
+
+/// A future which gets the first value out of whichever of several `RingQueue`s has one first.
+/// Created by `select_pop`.
+///
+/// The future only takes a value out of a queue when it completes, so dropping it before then
+/// never loses a value.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SelectFuture<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queues: &'a [&'a RingQueue<T, LEN, S, M>],
+    // The index of the queue to check first on the next poll.
+    next: usize,
+}
+
+impl<T, const LEN: usize, S, M> Future for SelectFuture<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Output = Result<(usize, T), Closed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        poll_select(this.queues, &mut this.next, cx)
+    }
+}
+
+// Pops a value from the first of `queues` which has one, starting from the queue at index `next`,
+// and registers `cx` to be woken by every queue if none of them does. `next` is then moved past
+// the queue that the value came from.
+pub(crate) fn poll_select<T, const LEN: usize, S, M>(
+    queues: &[&RingQueue<T, LEN, S, M>],
+    next: &mut usize,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(usize, T), Closed>>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    let len = queues.len();
+    let mut closed = 0;
+    for offset in 0..len {
+        let index = (*next + offset) % len;
+        match queues[index].poll_pop(cx) {
+            Poll::Ready(Ok(value)) => {
+                // Start after this queue next time so that a busy queue can't starve the rest.
+                *next = (index + 1) % len;
+                return Poll::Ready(Ok((index, value)));
+            }
+            Poll::Ready(Err(Closed)) => closed += 1,
+            Poll::Pending => {}
+        }
+    }
+    if closed == len {
+        Poll::Ready(Err(Closed))
+    } else {
+        Poll::Pending
+    }
+}
+
+/// Gets the first value out of whichever of `queues` has one first, along with the index of the
+/// queue that it came from. Queues are checked in turn starting from a different queue each time
+/// the future is polled, so that no queue is starved. The future resolves to `Err(Closed)` once
+/// every queue has been closed and emptied.
+pub fn select_pop<'a, T, const LEN: usize, S, M>(
+    queues: &'a [&'a RingQueue<T, LEN, S, M>],
+) -> SelectFuture<'a, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    SelectFuture { queues, next: 0 }
+}
+
+// The tasks waiting on one side of a queue.
+#[derive(Debug)]
+pub(crate) enum Wakers {
+    // Any number of tasks.
+    Many(Vec<Waker>),
+    // At most one task. Registering a task replaces whichever task was registered before it.
+    One(Option<Waker>),
+}
+
+impl Wakers {
+    // Creates an empty set of wakers with room for any number of tasks.
+    pub(crate) const fn new() -> Self {
+        Self::Many(Vec::new())
+    }
+
+    // Creates an empty set of wakers with room for the same number of tasks as `self`.
+    pub(crate) fn empty_like(&self) -> Self {
+        match self {
+            Self::Many(_) => Self::Many(vec![]),
+            Self::One(_) => Self::One(None),
+        }
+    }
+
+    // Arranges for the task which owns `waker` to be woken by the next call to `wake_all`.
+    pub(crate) fn register(&mut self, waker: &Waker) {
+        match self {
+            Self::Many(wakers) => {
+                if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+                    wakers.push(waker.clone());
+                }
+            }
+            Self::One(Some(registered)) if registered.will_wake(waker) => {}
+            Self::One(slot) => *slot = Some(waker.clone()),
+        }
+    }
+
+    // Wakes and forgets every registered task.
+    pub(crate) fn wake_all(&mut self) {
+        match self {
+            Self::Many(wakers) => wakers.drain(..).for_each(Waker::wake),
+            Self::One(slot) => slot.take().into_iter().for_each(Waker::wake),
+        }
+    }
+}
+
+impl Default for Wakers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Adds a new value to the end of the queue. The returned future waits while the queue is
+    /// full, regardless of the queue's `FullPolicy`, and resolves to the value if the queue is
+    /// closed. Does not depend on any particular async runtime. If the future is cancelled, the
+    /// value can be recovered with `PushFuture::into_inner`.
+    pub fn push_async(&self, value: T) -> PushFuture<'_, T, LEN, S, M> {
+        PushFuture {
+            queue: self,
+            value: Some(value),
+        }
+    }
+
+    /// Gets the first value out of the queue. The returned future waits while the queue is empty
+    /// and open, and resolves to `Err(Closed)` once the queue has been closed and every value in it
+    /// has been popped. Does not depend on any particular async runtime.
+    pub fn pop_async(&self) -> PopFuture<'_, T, LEN, S, M> {
+        PopFuture { queue: self }
+    }
+
+    /// Gets up to `n` values out of the queue, in order. The returned future waits while the queue
+    /// is empty and open, then takes as many values as are available, up to `n`, all at once. It
+    /// resolves to `Err(Closed)` once the queue has been closed and every value in it has been
+    /// popped.
+    pub fn pop_batch_async(&self, n: usize) -> PopBatchFuture<'_, T, LEN, S, M> {
+        PopBatchFuture { queue: self, n }
+    }
+
+    /// Takes the value out of `value` and adds it to the end of the queue if there is room for it.
+    /// Otherwise, leaves `value` alone, returns `Poll::Pending`, and arranges for the current task
+    /// to be woken once there might be room. Returns the value if the queue is closed.
+    ///
+    /// This is the building block of `push_async` for authors of hand-written futures and custom
+    /// runtimes, and never allocates beyond registering the task's waker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is `None`, unless the queue is open and full. In particular, it panics if
+    /// `value` is `None` and the queue is closed, since there is no value to hand back.
+    pub fn poll_push(
+        &self,
+        cx: &mut Context<'_>,
+        value: &mut Option<T>,
+    ) -> Poll<Result<(), T>> {
+        let mut inner = self.inner.lock();
+        if inner.closed {
+            return Poll::Ready(Err(value.take().expect("no value to push")));
+        }
+        if inner.is_full() {
+            inner.push_wakers.register(cx.waker());
+            return Poll::Pending;
+        }
+        inner.push(value.take().expect("no value to push"));
+        trace!("Pushed value into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, 1);
+        Poll::Ready(Ok(()))
+    }
+
+    // Returns `Poll::Ready` once a push would not have to wait, because the queue has room or is
+    // closed. Otherwise, arranges for the current task to be woken once there might be room.
+    #[cfg(feature = "futures")]
+    pub(crate) fn poll_room(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.lock();
+        if inner.is_full() && !inner.closed {
+            inner.push_wakers.register(cx.waker());
+            return Poll::Pending;
+        }
+        Poll::Ready(())
+    }
+
+    /// Gets the first value out of the queue if there is one. Otherwise, returns `Poll::Pending` and
+    /// arranges for the current task to be woken once there might be one. Returns `Err(Closed)`
+    /// once the queue has been closed and every value in it has been popped.
+    ///
+    /// This is the building block of `pop_async` for authors of hand-written futures and custom
+    /// runtimes, and never allocates beyond registering the task's waker.
+    pub fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Result<T, Closed>> {
+        let mut inner = self.inner.lock();
+        if inner.len() == 0 {
+            if inner.closed {
+                return Poll::Ready(Err(Closed));
+            }
+            inner.pop_wakers.register(cx.waker());
+            return Poll::Pending;
+        }
+        let ret = inner.pop();
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Poll::Ready(Ok(ret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::Arc,
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    // Wakes a task by unparking the thread which is running it.
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Runs `future` to completion on the current thread.
+    fn block_on<F>(future: F) -> F::Output
+    where
+        F: Future,
+    {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(ret) => return ret,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn async_halves_work_across_tasks() {
+        let (sender, receiver) = RingQueue::<u32, 2>::new().split();
+        let producer = thread::spawn(move || {
+            block_on(async {
+                for i in 0..10 {
+                    sender.push_async(i).await.unwrap();
+                }
+            })
+        });
+        let received = block_on(async {
+            let mut ret = vec![];
+            while let Ok(value) = receiver.pop_async().await {
+                ret.push(value);
+            }
+            ret
+        });
+        producer.join().unwrap();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn async_and_blocking_operations_wake_each_other() {
+        let queue = Arc::new(RingQueue::<u32, 1>::new());
+        let consumer = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || (0..5).map(|_| queue.pop().unwrap()).collect::<Vec<_>>())
+        };
+        block_on(async {
+            for i in 0..5 {
+                queue.push_async(i).await.unwrap();
+            }
+        });
+        assert_eq!(consumer.join().unwrap(), (0..5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn poll_functions_work_without_futures() {
+        let queue = RingQueue::<u32, 1>::new();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(queue.poll_pop(&mut cx), Poll::Pending);
+        let mut value = Some(3);
+        assert_eq!(queue.poll_push(&mut cx, &mut value), Poll::Ready(Ok(())));
+        assert_eq!(value, None);
+        let mut value = Some(4);
+        assert_eq!(queue.poll_push(&mut cx, &mut value), Poll::Pending);
+        assert_eq!(value, Some(4));
+        assert_eq!(queue.poll_pop(&mut cx), Poll::Ready(Ok(3)));
+    }
+
+    #[test]
+    fn spsc_queue_wakes_its_only_tasks() {
+        let (sender, receiver) = RingQueue::<u32, 1>::new_spsc().split();
+        let producer = thread::spawn(move || {
+            block_on(async {
+                for i in 0..10 {
+                    sender.push_async(i).await.unwrap();
+                }
+            })
+        });
+        let received = block_on(async {
+            let mut ret = vec![];
+            while let Ok(value) = receiver.pop_async().await {
+                ret.push(value);
+            }
+            ret
+        });
+        producer.join().unwrap();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cancelled_push_gives_value_back() {
+        let queue = RingQueue::<u32, 1>::new();
+        queue.push(3).unwrap();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut push = queue.push_async(4);
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Pending);
+        assert_eq!(push.into_inner(), Some(4));
+        let mut push = queue.push_async(5);
+        assert_eq!(queue.pop(), Ok(3));
+        assert_eq!(Pin::new(&mut push).poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(push.into_inner(), None);
+        assert_eq!(queue.drain(), vec![5]);
+    }
+
+    #[test]
+    fn pop_batch_async_takes_available_values_at_once() {
+        let queue = Arc::new(RingQueue::<u32, 8>::new());
+        let producer = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                queue.push_all(0..5).unwrap();
+                queue.close();
+            })
+        };
+        producer.join().unwrap();
+        assert_eq!(block_on(queue.pop_batch_async(3)), Ok(vec![0, 1, 2]));
+        assert_eq!(block_on(queue.pop_batch_async(3)), Ok(vec![3, 4]));
+        assert_eq!(block_on(queue.pop_batch_async(3)), Err(Closed));
+    }
+
+    #[test]
+    fn select_pop_takes_from_whichever_queue_is_ready() {
+        let first = Arc::new(RingQueue::<u32, 2>::new());
+        let second = Arc::new(RingQueue::<u32, 2>::new());
+        let producer = {
+            let second = Arc::clone(&second);
+            thread::spawn(move || {
+                second.push(3).unwrap();
+                second.close();
+            })
+        };
+        let queues = [&*first, &*second];
+        assert_eq!(block_on(select_pop(&queues)), Ok((1, 3)));
+        producer.join().unwrap();
+        first.push(4).unwrap();
+        assert_eq!(block_on(select_pop(&queues)), Ok((0, 4)));
+        first.close();
+        assert_eq!(block_on(select_pop(&queues)), Err(Closed));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn receiver_is_a_stream() {
+        use futures_core::Stream;
+        use std::future;
+
+        let (sender, mut receiver) = RingQueue::<u32, 4>::new().split();
+        (0..3).for_each(|i| sender.push(i).unwrap());
+        drop(sender);
+        let received = block_on(async {
+            let mut ret = vec![];
+            while let Some(value) =
+                future::poll_fn(|cx| Pin::new(&mut receiver).poll_next(cx)).await
+            {
+                ret.push(value);
+            }
+            ret
+        });
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn sender_is_a_sink() {
+        use futures_sink::Sink;
+        use std::future;
+
+        let (mut sender, receiver) = RingQueue::<u32, 1>::new().split();
+        let consumer = thread::spawn(move || receiver.iter().collect::<Vec<_>>());
+        block_on(async {
+            for i in 0..5 {
+                future::poll_fn(|cx| Pin::new(&mut sender).poll_ready(cx)).await.unwrap();
+                Pin::new(&mut sender).start_send(i).unwrap();
+            }
+            future::poll_fn(|cx| Pin::new(&mut sender).poll_close(cx)).await.unwrap();
+        });
+        drop(sender);
+        assert_eq!(consumer.join().unwrap(), (0..5).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn sink_is_not_ready_while_queue_is_full() {
+        use futures_sink::Sink;
+
+        let (mut sender, receiver) = RingQueue::<u32, 1>::new().split();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(Pin::new(&mut sender).poll_ready(&mut cx), Poll::Ready(Ok(())));
+        Pin::new(&mut sender).start_send(1).unwrap();
+        assert_eq!(Pin::new(&mut sender).poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(receiver.try_pop(), Ok(1));
+        assert_eq!(Pin::new(&mut sender).poll_ready(&mut cx), Poll::Ready(Ok(())));
+    }
+}
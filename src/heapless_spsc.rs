@@ -0,0 +1,220 @@
+//! An adapter which mirrors the API of `heapless::spsc` so that code written against it can switch
+//! to this crate by changing its imports. With the `std` feature the queue is a `RingQueue`, and
+//! the `Producer` and `Consumer` gain blocking methods; otherwise it is a
+//! `CriticalSectionRingQueue`.
+//!
+//! As with `heapless::spsc::Queue`, `Queue::new` is `const`, so a queue can be put in a `static`.
+//! Unlike it, there is no `peek`, because the first value cannot be borrowed past the end of the
+//! lock that protects it.
+
+#[cfg(not(feature = "std"))]
+use crate::CriticalSectionRingQueue as Backend;
+#[cfg(feature = "std")]
+use crate::{RingQueue as Backend, TryPushError};
+
+/// A fixed-size queue which can be split into a single `Producer` and a single `Consumer`.
+#[derive(Debug)]
+pub struct Queue<T, const LEN: usize> {
+    queue: Backend<T, LEN>,
+}
+
+impl<T, const LEN: usize> Queue<T, LEN> {
+    /// Create a new, empty `Queue`.
+    pub const fn new() -> Self {
+        Self {
+            queue: Backend::new(),
+        }
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    /// Adds a value to the end of the queue. Returns the value if the queue is full.
+    pub fn enqueue(&mut self, value: T) -> Result<(), T> {
+        try_push(&self.queue, value)
+    }
+
+    /// Removes the first value from the queue. Returns `None` if the queue is empty.
+    pub fn dequeue(&mut self) -> Option<T> {
+        try_pop(&self.queue)
+    }
+
+    /// Splits the queue into a `Producer` and a `Consumer` which can be used from different threads
+    /// or from an interrupt handler and the main loop.
+    pub fn split(&mut self) -> (Producer<'_, T, LEN>, Consumer<'_, T, LEN>) {
+        (
+            Producer { queue: &self.queue },
+            Consumer { queue: &self.queue },
+        )
+    }
+}
+
+impl<T, const LEN: usize> Default for Queue<T, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The only handle which may add values to a split `Queue`.
+#[derive(Debug)]
+pub struct Producer<'a, T, const LEN: usize> {
+    queue: &'a Backend<T, LEN>,
+}
+
+impl<T, const LEN: usize> Producer<'_, T, LEN> {
+    /// Whether there is room for another value in the queue.
+    pub fn ready(&self) -> bool {
+        !self.queue.is_full()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Adds a value to the end of the queue. Returns the value if the queue is full.
+    pub fn enqueue(&mut self, value: T) -> Result<(), T> {
+        try_push(self.queue, value)
+    }
+
+    /// Adds a value to the end of the queue, blocking while the queue is full.
+    #[cfg(feature = "std")]
+    pub fn enqueue_blocking(&mut self, value: T) {
+        // The queue is never closed, so `push` can only fail if it has a non-blocking
+        // `FullPolicy`, which a `Queue` never does.
+        if self.queue.push(value).is_err() {
+            unreachable!("`Queue` is never closed");
+        }
+    }
+}
+
+/// The only handle which may remove values from a split `Queue`.
+#[derive(Debug)]
+pub struct Consumer<'a, T, const LEN: usize> {
+    queue: &'a Backend<T, LEN>,
+}
+
+impl<T, const LEN: usize> Consumer<'_, T, LEN> {
+    /// Whether there is a value in the queue.
+    pub fn ready(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Removes the first value from the queue. Returns `None` if the queue is empty.
+    pub fn dequeue(&mut self) -> Option<T> {
+        try_pop(self.queue)
+    }
+
+    /// Removes the first value from the queue, blocking while the queue is empty.
+    #[cfg(feature = "std")]
+    pub fn dequeue_blocking(&mut self) -> T {
+        match self.queue.pop() {
+            Ok(value) => value,
+            Err(_) => unreachable!("`Queue` is never closed"),
+        }
+    }
+}
+
+// Adds a value to the end of `queue`, handing it back however the push failed.
+fn try_push<T, const LEN: usize>(queue: &Backend<T, LEN>, value: T) -> Result<(), T> {
+    #[cfg(feature = "std")]
+    return queue.try_push(value).map_err(TryPushError::into_inner);
+    #[cfg(not(feature = "std"))]
+    return queue.try_push(value);
+}
+
+// Removes the first value from `queue` if there is one.
+fn try_pop<T, const LEN: usize>(queue: &Backend<T, LEN>) -> Option<T> {
+    #[cfg(feature = "std")]
+    return queue.try_pop().ok();
+    #[cfg(not(feature = "std"))]
+    return queue.try_pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_halves_mirror_heapless() {
+        let mut queue = Queue::<u32, 2>::new();
+        assert_eq!(queue.enqueue(0), Ok(()));
+        assert_eq!(queue.dequeue(), Some(0));
+        let (mut producer, mut consumer) = queue.split();
+        assert!(!consumer.ready());
+        assert_eq!(producer.enqueue(1), Ok(()));
+        assert_eq!(producer.enqueue(2), Ok(()));
+        assert!(!producer.ready());
+        assert_eq!(producer.enqueue(3), Err(3));
+        assert_eq!(consumer.dequeue(), Some(1));
+        assert_eq!(consumer.len(), 1);
+        assert_eq!(consumer.dequeue(), Some(2));
+        assert_eq!(consumer.dequeue(), None);
+    }
+
+    #[test]
+    fn queue_can_be_static() {
+        static QUEUE: Queue<u32, 2> = Queue::new();
+        assert!(QUEUE.is_empty());
+        assert_eq!(QUEUE.capacity(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking_halves_work_across_threads() {
+        let mut queue = Queue::<u32, 1>::new();
+        let (mut producer, mut consumer) = queue.split();
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..10 {
+                    producer.enqueue_blocking(i);
+                }
+            });
+            for i in 0..10 {
+                assert_eq!(consumer.dequeue_blocking(), i);
+            }
+        });
+    }
+}
@@ -1,189 +1,2148 @@
 //! A concurrent queue that uses a ring buffer to store its values.
+//!
+//! Everything other than the ring buffers needs the `std` feature, which is enabled by default.
+//! Without it the crate is `no_std` and does not allocate. The `critical-section` feature adds
+//! `CriticalSectionRingQueue`, which can be shared with interrupt handlers on bare metal. The
+//! `parking_lot` feature makes the blocking queues lock `parking_lot`'s mutexes instead of `std`'s.
+//! `RingQueue` can instead be locked by any `lock_api::RawMutex` through `Builder::raw_mutex`. The
+//! `lock_api` feature adds `RawMutexRingQueue`, which is also locked by a mutex of the user's
+//! choice but doesn't need `std` and can only be pushed to and popped from without waiting, and the
+//! `spin` feature adds `SpinRingQueue`, which is locked by a spinlock. The `registry` feature adds
+//! the `registry` module, where queues can be shared by name. The `crossbeam-channel` and `tokio`
+//! features add adapters to the `bridge` module for moving values between queues and those crates'
+//! channels.
+//!
+//! # Minimum supported Rust version
+//!
+//! The crate needs Rust 1.82 or later. Every queue, and the ring buffer that they share, is
+//! generic over its capacity with const generics, so there is no front end for toolchains that
+//! predate them: a macro-generated or `typenum`-based set of capacities would still have to be
+//! built on top of the const-generic internals, which those toolchains can't compile.
 
-#![feature(
-    box_syntax,
-    maybe_uninit_ref,
-    maybe_uninit_uninit_array,
-    min_const_generics,
-    thread_spawn_unchecked
-)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_debug_implementations, rust_2018_idioms)]
 
-use std::{fmt::Debug, mem::{self, MaybeUninit}, sync::{Condvar, Mutex}};
+// Emits a trace-level message through `defmt`, `log`, and `tracing` when the respective features
+// are enabled.
+#[cfg_attr(not(feature = "std"), allow(unused_macros))]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        defmt::trace!($($arg)*);
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+// Enters a trace-level `tracing` span which lasts until the end of the enclosing block when the
+// `tracing` feature is enabled.
+#[cfg_attr(not(feature = "std"), allow(unused_macros))]
+macro_rules! span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!($name).entered();
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod broadcast;
+#[cfg(feature = "std")]
+pub mod bridge;
+#[cfg(feature = "std")]
+pub mod disruptor;
+#[cfg(feature = "std")]
+pub mod fallible;
+#[cfg(any(feature = "std", feature = "critical-section"))]
+pub mod heapless_spsc;
+pub mod mpmc;
+#[cfg(feature = "registry")]
+pub mod registry;
+pub mod spsc;
+
+#[cfg(feature = "critical-section")]
+mod bare_metal;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod channel;
+#[cfg(feature = "std")]
+mod dynamic;
+mod error;
+#[cfg(feature = "std")]
+mod future;
+#[cfg(feature = "std")]
+mod locked;
+#[cfg(feature = "std")]
+mod overwriting;
+mod padding;
+#[cfg(feature = "lock_api")]
+mod raw_mutex;
+mod ring;
+#[cfg(feature = "std")]
+mod select;
+#[cfg(feature = "std")]
+mod slot;
+#[cfg(feature = "spin")]
+mod spin;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "std")]
+mod two_lock;
+#[cfg(feature = "std")]
+mod watch;
+
+#[cfg(feature = "critical-section")]
+pub use bare_metal::CriticalSectionRingQueue;
+#[cfg(feature = "std")]
+pub use builder::{Builder, FullPolicy, WaitStrategy};
+
+#[cfg(feature = "std")]
+use builder::EvictCallback;
+#[cfg(feature = "std")]
+use future::Wakers;
+#[cfg(feature = "std")]
+use padding::CachePadded;
+#[cfg(feature = "std")]
+use sync::RawCondvar;
+#[cfg(feature = "std")]
+pub use sync::DefaultRawMutex;
+#[cfg(feature = "std")]
+pub use channel::{channel, Disconnected, Receiver, ReceiverIntoIter, SendError, Sender};
+#[cfg(feature = "std")]
+pub use dynamic::DynRingQueue;
+pub use error::{PopError, PopTimeoutError, PushError, PushTimeoutError, TryPushError};
+#[cfg(feature = "std")]
+pub use future::{select_pop, PopBatchFuture, PopFuture, PushFuture, SelectFuture};
+#[cfg(feature = "std")]
+pub use locked::{LockedQueue, Transaction};
+#[cfg(feature = "std")]
+pub use overwriting::OverwritingRingQueue;
+#[cfg(feature = "lock_api")]
+pub use raw_mutex::RawMutexRingQueue;
+#[cfg(feature = "std")]
+pub use ring::BoxedRingBuffer;
+pub use ring::{RingBuffer, SliceRingBuffer, Storage, StorageRingBuffer};
+#[cfg(feature = "std")]
+pub use select::Select;
+#[cfg(feature = "std")]
+pub use slot::{ContiguousGuard, PopGuard, PushSlot, SlicesGuard};
+#[cfg(feature = "spin")]
+pub use spin::{SpinRawMutex, SpinRingQueue};
+#[cfg(feature = "std")]
+pub use two_lock::TwoLockRingQueue;
+#[cfg(feature = "std")]
+pub use watch::WatchQueue;
+
+#[cfg(feature = "std")]
+use core::{
+    hint,
+    mem::{self, MaybeUninit},
+};
+use core::fmt::{self, Display, Formatter};
+#[cfg(feature = "std")]
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    error::Error,
+    iter::FromIterator,
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 
-/// A concurrent fixed-size queue.
+#[cfg(feature = "std")]
+use lock_api::{Mutex, MutexGuard, RawMutex};
+
+// The most spins that `WaitStrategy::Adaptive` will expect a wait to take.
+#[cfg(feature = "std")]
+const MAX_ADAPTIVE_SPINS: u32 = 1000;
+
+/// A concurrent fixed-size queue. Its values are kept in `S`, which is an array inside the queue
+/// unless the queue was created by `RingQueue::new_boxed`, and it is locked by an `M`, which is a
+/// `DefaultRawMutex` unless the queue was created with `Builder::raw_mutex`.
+///
+/// A queue can only be shared between threads if its values can be sent between them, since one
+/// thread may pop a value that another pushed:
+///
+/// ```compile_fail
+/// use ring_queue::RingQueue;
+/// use std::rc::Rc;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<RingQueue<Rc<u32>, 4>>();
+/// ```
+///
+/// ```compile_fail
+/// use ring_queue::RingQueue;
+/// use std::{rc::Rc, thread};
+///
+/// let queue = RingQueue::<Rc<u32>, 4>::new();
+/// thread::scope(|scope| {
+///     scope.spawn(|| queue.push(Rc::new(3)));
+/// });
+/// ```
+#[cfg(feature = "std")]
 #[derive(Debug)]
-pub struct RingQueue<T, const LEN: usize> {
+pub struct RingQueue<T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
     // All the stuff that needs to be synchronized.
-    inner: Mutex<Inner<T, LEN>>,
-    // The condition to wait on in the `pop` function.
-    pop_cond: Condvar,
-    // The condition to wait on in the `push` function.
-    push_cond: Condvar,
+    inner: Mutex<M, Inner<T, S>>,
+    // The condition to wait on in the `pop` function. Consumers wait on it while producers notify
+    // it, so it gets its own cache line.
+    pop_cond: CachePadded<RawCondvar>,
+    // The condition to wait on in the `push` function. Every waiter is woken whenever room is made
+    // because a thread in `push_all` may be waiting for more room than was made.
+    push_cond: CachePadded<RawCondvar>,
+    // What `push` does when the queue is full.
+    full_policy: FullPolicy,
+    // The function to give values discarded by `full_policy`.
+    on_evict: Option<EvictCallback<T>>,
+    // How blocking operations wait before, or instead of, waiting on a condvar.
+    wait_strategy: WaitStrategy,
+    // How many spins recent waits have taken. Only used by `WaitStrategy::Adaptive`.
+    spin_estimate: AtomicU32,
+}
+
+/// A `RingQueue` which keeps its values on the heap instead of inside itself. Created by
+/// `RingQueue::new_boxed`.
+#[cfg(feature = "std")]
+pub type BoxedRingQueue<T, const LEN: usize, M = DefaultRawMutex> =
+    RingQueue<T, LEN, Box<[MaybeUninit<T>; LEN]>, M>;
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    /// Create a new `RingQueue`. This is a `const fn`, so a queue can be put in a `static` and
+    /// used from anywhere without any lazy initialization:
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// static LOG_QUEUE: RingQueue<&str, 1024> = RingQueue::new();
+    ///
+    /// LOG_QUEUE.push("started").unwrap();
+    /// assert_eq!(LOG_QUEUE.try_pop(), Ok("started"));
+    /// ```
+    pub const fn new() -> Self {
+        // This must match what `Builder::build` creates from a default `Builder`, which can't be
+        // used here because it isn't `const`.
+        Self {
+            inner: Mutex::new(Inner::new([const { MaybeUninit::uninit() }; LEN])),
+            pop_cond: CachePadded(RawCondvar::new()),
+            push_cond: CachePadded(RawCondvar::new()),
+            full_policy: FullPolicy::Block,
+            on_evict: None,
+            wait_strategy: WaitStrategy::Park,
+            spin_estimate: AtomicU32::new(0),
+        }
+    }
+
+    /// Create a new `RingQueue` which keeps its values on the heap. The values are never placed
+    /// on the stack, even temporarily, so this is the way to create a queue that is too large for
+    /// the stack. See `Builder::build_boxed`.
+    pub fn new_boxed() -> BoxedRingQueue<T, LEN, M> {
+        Self::builder().build_boxed()
+    }
+
+    /// Create a new `RingQueue` which is optimized for use by exactly one async producer and one
+    /// async consumer. See `Builder::spsc`.
+    pub fn new_spsc() -> Self {
+        Self::builder().spsc().build()
+    }
+
+    /// Create a `Builder` for configuring a new `RingQueue`.
+    pub fn builder() -> Builder<T, LEN, M> {
+        Builder::new().raw_mutex()
+    }
 }
 
-impl<T, const LEN: usize> RingQueue<T, LEN>
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
 where
-    T: Debug,
+    S: Storage<T>,
+    M: RawMutex,
 {
-    /// Create a new `RingQueue`.
-    pub fn new() -> Self {
-        Self::default()
+    /// The maximum number of values that a `RingQueue` of this type can hold.
+    pub const CAPACITY: usize = LEN;
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.lock().len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.inner.lock().is_full()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
     }
 
-    /// Gets the first value out of the queue. Blocks while the queue is empty.
-    pub fn pop(&self) -> T {
-        let mut inner = self.pop_cond
-            .wait_while(self.inner.lock().unwrap(), |inner| inner.size == 0)
-            .unwrap();
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and every value in it has been popped.
+    pub fn pop(&self) -> Result<T, Closed> {
+        span!("pop");
+        let mut inner = self.lock_while(&self.pop_cond, |inner| inner.len() == 0 && !inner.closed);
+        if inner.len() == 0 {
+            return Err(Closed);
+        }
         let ret = inner.pop();
-        self.push_cond.notify_one();
-        println!("Popping {:?} from queue", ret);
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Ok(ret)
+    }
+
+    /// Gets the first `n` values out of the queue, in order. Blocks until `n` values have been
+    /// popped, taking as many values as are available each time the queue is locked. Returns fewer
+    /// than `n` values if the queue is closed and empties first.
+    pub fn pop_many(&self, n: usize) -> Vec<T> {
+        span!("pop_many");
+        let mut ret = Vec::with_capacity(n);
+        let mut inner = self.inner.lock();
+        loop {
+            while ret.len() < n && inner.len() > 0 {
+                ret.push(inner.pop());
+            }
+            if ret.len() == n || inner.closed {
+                break;
+            }
+            self.notify_popped(&mut inner);
+            drop(inner);
+            inner = self.lock_while(&self.pop_cond, |inner| inner.len() == 0 && !inner.closed);
+        }
+        trace!("Popped {} values from queue, {} remaining", ret.len(), inner.len());
+        self.notify_popped(&mut inner);
+        ret
+    }
+
+    /// Gets up to `n` values out of the queue, in order, without blocking. Returns an empty `Vec`
+    /// if the queue is empty.
+    pub fn pop_up_to(&self, n: usize) -> Vec<T> {
+        span!("pop_up_to");
+        let mut inner = self.inner.lock();
+        let mut ret = Vec::with_capacity(n.min(inner.len()));
+        while ret.len() < n && inner.len() > 0 {
+            ret.push(inner.pop());
+        }
+        trace!("Popped {} values from queue, {} remaining", ret.len(), inner.len());
+        self.notify_popped(&mut inner);
         ret
     }
 
-    /// Adds a new value to the end of the queue. Blocks while the queue is full.
-    pub fn push(&self, value: T) {
-        println!("Pushing {:?} into queue", value);
-        let mut inner = self.push_cond
-            .wait_while(self.inner.lock().unwrap(), |inner| inner.size == LEN)
-            .unwrap();
+    /// Gets the first value out of the queue if there is one. Returns `PopError::Empty`
+    /// immediately if the queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        span!("try_pop");
+        let mut inner = self.inner.lock();
+        if inner.len() == 0 {
+            return Err(if inner.closed {
+                PopError::Closed
+            } else {
+                PopError::Empty
+            });
+        }
+        let ret = inner.pop();
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Ok(ret)
+    }
+
+    /// Gets the first value out of the queue if there is one and `pred` approves of it, without
+    /// blocking. Returns `None` without calling `pred` if the queue is empty. The queue stays locked
+    /// while `pred` runs, so the value it sees is the value that is popped.
+    pub fn pop_if<F>(&self, pred: F) -> Option<T>
+    where
+        F: FnOnce(&T) -> bool,
+    {
+        span!("pop_if");
+        let mut inner = self.inner.lock();
+        if !inner.front().is_some_and(pred) {
+            return None;
+        }
+        let ret = inner.pop();
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Some(ret)
+    }
+
+    /// Gets the first value out of the queue once `pred` approves of it, leaving other values for
+    /// other consumers. Blocks until the first value in the queue is approved, checking again
+    /// whenever the first value changes. Returns `Err(Closed)` once the queue has been closed and
+    /// every value in it has been popped; until then, values that `pred` rejects still have to be
+    /// popped by some other consumer.
+    pub fn pop_when<F>(&self, pred: F) -> Result<T, Closed>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        span!("pop_when");
+        let mut pred = pred;
+        self.inner.lock().front_waiters += 1;
+        let waiter = FrontWaiter(&self.inner);
+        let mut inner = self.lock_while(&self.pop_cond, |inner| match inner.front() {
+            Some(value) => !pred(value),
+            None => !inner.closed,
+        });
+        inner.front_waiters -= 1;
+        mem::forget(waiter);
+        if inner.len() == 0 {
+            return Err(Closed);
+        }
+        let ret = inner.pop();
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Ok(ret)
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but for no
+    /// longer than `timeout`. Returns `PopTimeoutError::Timeout` if the queue was still empty when
+    /// the timeout elapsed, or `PopTimeoutError::Closed` if the queue was closed and empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        span!("pop_timeout");
+        let mut inner = self.lock_timeout_while(&self.pop_cond, timeout, |inner| {
+            inner.len() == 0 && !inner.closed
+        });
+        if inner.len() == 0 {
+            return Err(if inner.closed {
+                PopTimeoutError::Closed
+            } else {
+                PopTimeoutError::Timeout
+            });
+        }
+        let ret = inner.pop();
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        Ok(ret)
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but not
+    /// past `deadline`. Fails in the same ways as `pop_timeout`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.pop_timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Adds a new value to the end of the queue. If the queue is full, what happens depends on the
+    /// queue's `FullPolicy`, which blocks while the queue is full by default. Returns the value in
+    /// `PushError::Closed` if the queue is closed, or in `PushError::Full` if it is full and the
+    /// policy is `FullPolicy::Reject`.
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        span!("push");
+        let mut inner = match self.full_policy {
+            FullPolicy::Block => self.lock_while(&self.push_cond, |inner| {
+                inner.is_full() && !inner.closed
+            }),
+            _ => self.inner.lock(),
+        };
+        if inner.closed {
+            return Err(PushError::Closed(value));
+        }
+        let mut evicted = None;
+        if inner.is_full() {
+            match self.full_policy {
+                FullPolicy::Block => unreachable!("waited for room in the queue"),
+                FullPolicy::DropOldest if inner.capacity() > 0 => {
+                    evicted = Some(inner.pop());
+                    trace!("Dropped oldest value from full queue");
+                }
+                // A queue with no room at all holds no older value, so the pushed value is also
+                // the oldest one.
+                FullPolicy::DropNewest | FullPolicy::DropOldest => {
+                    drop(inner);
+                    trace!("Dropped value pushed into full queue");
+                    self.evict(value);
+                    return Ok(());
+                }
+                FullPolicy::Reject => return Err(PushError::Full(value)),
+            }
+        }
+        inner.push(value);
+        trace!("Pushed value into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, 1);
+        drop(inner);
+        if let Some(evicted) = evicted {
+            self.evict(evicted);
+        }
+        Ok(())
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch. Blocks until
+    /// there is room for the whole batch. Returns the values if the queue is closed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more values than the queue's capacity, since they could never fit in the
+    /// queue at once.
+    pub fn push_all<I>(&self, values: I) -> Result<(), Vec<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        span!("push_all");
+        let values = values.into_iter().collect::<Vec<_>>();
+        let capacity = self.inner.lock().capacity();
+        assert!(
+            values.len() <= capacity,
+            "cannot push {} values into a queue of capacity {}",
+            values.len(),
+            capacity,
+        );
+        let mut inner = self.lock_while(&self.push_cond, |inner| {
+            inner.capacity() - inner.len() < values.len() && !inner.closed
+        });
+        if inner.closed {
+            return Err(values);
+        }
+        let pushed = values.len();
+        for value in values {
+            inner.push(value);
+        }
+        trace!("Pushed batch into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, pushed);
+        Ok(())
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch if there is
+    /// room for the whole batch. Returns the values untouched, without blocking, if the queue does
+    /// not have room for all of them or is closed.
+    pub fn try_push_all<I>(&self, values: I) -> Result<(), Vec<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        span!("try_push_all");
+        let values = values.into_iter().collect::<Vec<_>>();
+        let mut inner = self.inner.lock();
+        if inner.capacity() - inner.len() < values.len() || inner.closed {
+            return Err(values);
+        }
+        let pushed = values.len();
+        for value in values {
+            inner.push(value);
+        }
+        trace!("Pushed batch into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, pushed);
+        Ok(())
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value
+    /// immediately in `TryPushError::Full` or `TryPushError::Closed` if the queue is full or
+    /// closed.
+    ///
+    /// This method still takes the queue's lock, which may be held by another thread, so it must
+    /// not be called from an interrupt handler. Use `CriticalSectionRingQueue::try_push` there.
+    pub fn try_push(&self, value: T) -> Result<(), TryPushError<T>> {
+        span!("try_push");
+        let mut inner = self.inner.lock();
+        if inner.closed {
+            return Err(TryPushError::Closed(value));
+        }
+        if inner.is_full() {
+            return Err(TryPushError::Full(value));
+        }
+        inner.push(value);
+        trace!("Pushed value into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, 1);
+        Ok(())
+    }
+
+    /// Adds a new value to the end of the queue without blocking, evicting the first value if the
+    /// queue is full. Returns the value which did not make it into the queue: the evicted value if
+    /// there was one, or `value` itself if the queue is closed.
+    pub fn force_push(&self, value: T) -> Option<T> {
+        span!("force_push");
+        let mut inner = self.inner.lock();
+        if inner.closed || inner.capacity() == 0 {
+            return Some(value);
+        }
+        let evicted = if inner.is_full() {
+            Some(inner.pop())
+        } else {
+            None
+        };
         inner.push(value);
-        self.pop_cond.notify_one();
+        trace!("Force-pushed value into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, 1);
+        evicted
+    }
+
+    /// Adds a new value to the end of the queue. Blocks while the queue is full, but for no longer
+    /// than `timeout`. Returns the value in `PushTimeoutError::Timeout` if the queue was still full
+    /// when the timeout elapsed, or in `PushTimeoutError::Closed` if the queue is closed.
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        span!("push_timeout");
+        let mut inner = self.lock_timeout_while(&self.push_cond, timeout, |inner| {
+            inner.is_full() && !inner.closed
+        });
+        if inner.closed {
+            return Err(PushTimeoutError::Closed(value));
+        }
+        if inner.is_full() {
+            return Err(PushTimeoutError::Timeout(value));
+        }
+        inner.push(value);
+        trace!("Pushed value into queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, 1);
+        Ok(())
+    }
+
+    /// Adds a new value to the end of the queue. Blocks while the queue is full, but not past
+    /// `deadline`. Fails in the same ways as `push_timeout`.
+    pub fn push_until(&self, value: T, deadline: Instant) -> Result<(), PushTimeoutError<T>> {
+        self.push_timeout(value, deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Copies as many values from the front of `values` to the end of the queue as there is room
+    /// for, without blocking and regardless of the queue's `FullPolicy`. Returns the number of
+    /// values copied, or `Err(Closed)` if the queue is closed.
+    pub fn push_slice(&self, values: &[T]) -> Result<usize, Closed>
+    where
+        T: Copy,
+    {
+        span!("push_slice");
+        let mut inner = self.inner.lock();
+        if inner.closed {
+            return Err(Closed);
+        }
+        let pushed = inner.ring.push_slice(values);
+        trace!("Pushed {} values into queue, {} queued", pushed, inner.len());
+        if pushed > 0 {
+            self.notify_pushed(&mut inner, pushed);
+        }
+        Ok(pushed)
+    }
+
+    /// Moves as many values from the front of the queue into the front of `out` as fit, without
+    /// blocking. Returns the number of values moved, which is 0 if the queue is empty.
+    pub fn pop_slice(&self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        span!("pop_slice");
+        let mut inner = self.inner.lock();
+        let popped = inner.ring.pop_slice(out);
+        trace!("Popped {} values from queue, {} remaining", popped, inner.len());
+        if popped > 0 {
+            self.notify_popped(&mut inner);
+        }
+        popped
+    }
+
+    /// Removes every value that is currently in the queue, in order, without blocking.
+    pub fn drain(&self) -> Vec<T> {
+        self.pop_up_to(usize::MAX)
+    }
+
+    /// Removes and drops every value in the queue, then wakes every thread waiting to push. The
+    /// values are dropped while the queue is locked.
+    pub fn clear(&self) {
+        span!("clear");
+        let mut inner = self.inner.lock();
+        let len = inner.len();
+        inner.ring.clear();
+        trace!("Removed {} values from queue", len);
+        if len > 0 {
+            self.notify_popped(&mut inner);
+        }
+    }
+
+    /// Drops the newest values in the queue so that only the oldest `len` are left, then wakes
+    /// every thread waiting to push if any values were dropped. Use `truncate_front` to keep the
+    /// newest values instead.
+    pub fn truncate(&self, len: usize) {
+        span!("truncate");
+        let mut inner = self.inner.lock();
+        let old_len = inner.len();
+        inner.ring.truncate(len);
+        trace!("Removed {} values from queue, {} remaining", old_len - inner.len(), inner.len());
+        if inner.len() < old_len {
+            self.notify_popped(&mut inner);
+        }
+    }
+
+    /// Drops the oldest values in the queue so that only the newest `len` are left, then wakes
+    /// every thread waiting to push if any values were dropped.
+    pub fn truncate_front(&self, len: usize) {
+        span!("truncate_front");
+        let mut inner = self.inner.lock();
+        let old_len = inner.len();
+        inner.ring.truncate_front(len);
+        trace!("Removed {} values from queue, {} remaining", old_len - inner.len(), inner.len());
+        if inner.len() < old_len {
+            self.notify_popped(&mut inner);
+        }
+    }
+
+    /// Removes every value in the queue for which `f` returns `false`, keeping the rest in order.
+    /// The queue stays locked while `f` runs, and threads waiting to push are woken if any values
+    /// were removed.
+    pub fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        span!("retain");
+        let mut inner = self.inner.lock();
+        let len = inner.len();
+        inner.ring.retain(f);
+        trace!("Removed {} values from queue, {} remaining", len - inner.len(), inner.len());
+        if inner.len() < len {
+            self.notify_popped(&mut inner);
+        }
+    }
+
+    /// Calls `f` on every value in the queue, in order, and removes the values for which it returns
+    /// `false`. The values that are kept stay in order along with any changes `f` made to them.
+    /// The queue stays locked while `f` runs, and threads waiting to push are woken if any values
+    /// were removed.
+    pub fn retain_mut<F>(&self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        span!("retain_mut");
+        let mut inner = self.inner.lock();
+        let len = inner.len();
+        inner.ring.retain_mut(f);
+        trace!("Removed {} values from queue, {} remaining", len - inner.len(), inner.len());
+        if inner.len() < len {
+            self.notify_popped(&mut inner);
+        }
+    }
+
+    /// Gets a copy of the first value in the queue without removing it. Returns `None` if the
+    /// queue is empty.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.peek_with(T::clone)
+    }
+
+    /// Calls `f` on the first value in the queue without removing it. Returns `None` without
+    /// calling `f` if the queue is empty. The queue stays locked while `f` runs.
+    pub fn peek_with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.inner.lock().front().map(f)
+    }
+
+    /// Consumes the queue, returning the values that were left in it in order.
+    pub fn into_vec(self) -> Vec<T> {
+        self.into_iter().collect()
+    }
+
+    /// Gets a copy of the value `index` values from the front of the queue without removing it.
+    /// Returns `None` if there are not that many values in the queue.
+    pub fn get(&self, index: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.get_with(index, T::clone)
     }
+
+    /// Calls `f` on the value `index` values from the front of the queue without removing it.
+    /// Returns `None` without calling `f` if there are not that many values in the queue. The
+    /// queue stays locked while `f` runs.
+    pub fn get_with<F, R>(&self, index: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.inner.lock().ring.get(index).map(f)
+    }
+
+    /// Gets a copy of every value that is currently in the queue, in order, without removing them.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let inner = self.inner.lock();
+        let (first, second) = inner.ring.as_slices();
+        let mut ret = Vec::with_capacity(first.len() + second.len());
+        ret.extend_from_slice(first);
+        ret.extend_from_slice(second);
+        ret
+    }
+
+    /// Copies as many of the values that are currently in the queue as fit into the front of
+    /// `out`, in order, without removing them. Returns the number of values copied.
+    pub fn copy_to_slice(&self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        self.inner.lock().ring.copy_to_slice(out)
+    }
+
+    /// Calls `f` on every value that is currently in the queue, in order, without removing them.
+    /// The queue stays locked while `f` runs.
+    pub fn inspect<F>(&self, f: F)
+    where
+        F: FnMut(&T),
+    {
+        let inner = self.inner.lock();
+        let (first, second) = inner.ring.as_slices();
+        first.iter().chain(second).for_each(f);
+    }
+
+    /// Whether any value in the queue is equal to `value`.
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.position(|queued| queued == value).is_some()
+    }
+
+    /// Gets a copy of the first value in the queue for which `pred` returns `true`, without
+    /// removing it. Returns `None` if there is no such value. The queue stays locked while `pred`
+    /// runs.
+    pub fn find<F>(&self, pred: F) -> Option<T>
+    where
+        T: Clone,
+        F: FnMut(&T) -> bool,
+    {
+        let mut pred = pred;
+        let inner = self.inner.lock();
+        let (first, second) = inner.ring.as_slices();
+        first.iter().chain(second).find(|value| pred(value)).cloned()
+    }
+
+    /// Gets how many values are in front of the first value in the queue for which `pred` returns
+    /// `true`. Returns `None` if there is no such value. The queue stays locked while `pred` runs.
+    pub fn position<F>(&self, pred: F) -> Option<usize>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let inner = self.inner.lock();
+        let (first, second) = inner.ring.as_slices();
+        first.iter().chain(second).position(pred)
+    }
+
+    /// Returns an iterator which pops values out of the queue, blocking while it is empty. The
+    /// iterator ends once the queue has been closed and every value in it has been popped.
+    pub fn iter(&self) -> Iter<'_, T, LEN, S, M> {
+        Iter { queue: self }
+    }
+
+    /// Returns an iterator which pops the values that are currently in the queue. The iterator
+    /// ends as soon as the queue is empty instead of blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T, LEN, S, M> {
+        TryIter { queue: self }
+    }
+
+    /// Closes the queue. Values that are already in the queue can still be popped, but every
+    /// attempt to push a new value will fail and every thread blocked in `push` or `pop` is woken.
+    pub fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        // Every waiter has to see that the queue is closed, not just one of them.
+        self.notify_pushed(&mut inner, usize::MAX);
+        self.notify_popped(&mut inner);
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().closed
+    }
+
+    // Wakes the threads and tasks waiting to pop now that `pushed` values have been pushed.
+    fn notify_pushed(&self, inner: &mut Inner<T, S>, pushed: usize) {
+        // A thread in `pop_when` might not take the value, so it can't be the only one woken.
+        if pushed == 1 && inner.front_waiters == 0 {
+            self.pop_cond.notify_one();
+        } else {
+            self.pop_cond.notify_all();
+        }
+        inner.pop_wakers.wake_all();
+    }
+
+    // Wakes the threads and tasks waiting to push now that values have been popped.
+    fn notify_popped(&self, inner: &mut Inner<T, S>) {
+        self.push_cond.notify_all();
+        if inner.front_waiters > 0 {
+            self.pop_cond.notify_all();
+        }
+        inner.push_wakers.wake_all();
+    }
+
+    // Gives a value discarded by `self.full_policy` to the eviction callback. Must not be called
+    // while the queue is locked so that the callback may use the queue.
+    fn evict(&self, value: T) {
+        if let Some(on_evict) = &self.on_evict {
+            (on_evict.0)(value);
+        }
+    }
+
+    // Locks the queue, then blocks on `cond` for as long as `condition` holds.
+    fn lock_while<F>(
+        &self,
+        cond: &RawCondvar,
+        condition: F,
+    ) -> MutexGuard<'_, M, Inner<T, S>>
+    where
+        F: FnMut(&mut Inner<T, S>) -> bool,
+    {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let mut condition = condition;
+        let inner = self.lock_spinning(&mut condition, None);
+        let inner = cond.wait_while(inner, condition);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(blocked = ?started.elapsed(), "Acquired queue");
+        inner
+    }
+
+    // Locks the queue, then blocks on `cond` for as long as `condition` holds, but for no longer
+    // than `timeout`.
+    fn lock_timeout_while<F>(
+        &self,
+        cond: &RawCondvar,
+        timeout: Duration,
+        condition: F,
+    ) -> MutexGuard<'_, M, Inner<T, S>>
+    where
+        F: FnMut(&mut Inner<T, S>) -> bool,
+    {
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let mut condition = condition;
+        let deadline = Instant::now().checked_add(timeout);
+        let inner = self.lock_spinning(&mut condition, deadline);
+        let timeout = deadline.map_or(timeout, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        let (inner, _) = cond.wait_timeout_while(inner, timeout, condition);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(blocked = ?started.elapsed(), "Acquired queue");
+        inner
+    }
+
+    // Locks the queue, then checks `condition` again, releasing the lock in between, until it
+    // stops holding, `deadline` passes, or `self.wait_strategy` says to park on a condvar instead.
+    // Waits that end within a few iterations then never touch the condvar. Returns the queue still
+    // locked whether or not the condition holds.
+    fn lock_spinning<F>(
+        &self,
+        condition: &mut F,
+        deadline: Option<Instant>,
+    ) -> MutexGuard<'_, M, Inner<T, S>>
+    where
+        F: FnMut(&mut Inner<T, S>) -> bool,
+    {
+        let spin_limit = match self.wait_strategy {
+            WaitStrategy::BusySpin | WaitStrategy::Yield => None,
+            WaitStrategy::SpinThenPark(limit) => Some(limit),
+            // Leave room above the estimate so that it can grow when waits get longer.
+            WaitStrategy::Adaptive => Some(self.spin_estimate.load(Ordering::Relaxed) * 2 + 10),
+            WaitStrategy::Park => Some(0),
+        };
+        let mut inner = self.inner.lock();
+        let mut spins = 0u32;
+        let mut parking = false;
+        while condition(&mut inner) {
+            if spin_limit.is_some_and(|limit| spins >= limit)
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                parking = true;
+                break;
+            }
+            drop(inner);
+            if self.wait_strategy == WaitStrategy::Yield {
+                thread::yield_now();
+            } else {
+                hint::spin_loop();
+            }
+            spins = spins.saturating_add(1);
+            inner = self.inner.lock();
+        }
+        if self.wait_strategy == WaitStrategy::Adaptive && (parking || spins > 0) {
+            // A wait that outlasted the spinning counts as needing no spins at all, since spinning
+            // through it would only have wasted time.
+            self.adapt_spin_estimate(if parking { 0 } else { spins });
+        }
+        inner
+    }
+
+    // Moves the number of spins that `WaitStrategy::Adaptive` expects a wait to take an eighth of
+    // the way towards the number that the latest wait took, so that it follows recent waits
+    // without being thrown off by any single one.
+    fn adapt_spin_estimate(&self, spins: u32) {
+        let spins = spins.min(MAX_ADAPTIVE_SPINS);
+        // Losing an update to a race with another waiter only makes the estimate lag a little.
+        let estimate = self.spin_estimate.load(Ordering::Relaxed);
+        let estimate = if spins > estimate {
+            estimate + (spins - estimate) / 8
+        } else {
+            estimate - (estimate - spins) / 8
+        };
+        self.spin_estimate.store(estimate, Ordering::Relaxed);
+    }
+}
+
+/// A blocking iterator over the values popped from a `RingQueue`. Created by `RingQueue::iter`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Iter<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
 }
 
-// SAFETY: Calling this function is only safe when `value` is initialized.
-unsafe fn clone_initialized_uninit<T>(value: &MaybeUninit<T>) -> MaybeUninit<T>
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> Iterator for Iter<'_, T, LEN, S, M>
 where
-    T: Clone,
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop().ok()
+    }
+}
+
+/// A non-blocking iterator over the values popped from a `RingQueue`. Created by
+/// `RingQueue::try_iter`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct TryIter<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
 {
-    MaybeUninit::new(value.assume_init_ref().clone())
+    queue: &'a RingQueue<T, LEN, S, M>,
 }
 
-impl<T, const LEN: usize> Clone for RingQueue<T, LEN>
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> Iterator for TryIter<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.try_pop().ok()
+    }
+}
+
+/// An iterator over the values that were left in a `RingQueue`, in order. Created by
+/// `RingQueue::into_iter`. Dropping it drops the values that have yet to be yielded.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IntoIter<T, const LEN: usize, S = [MaybeUninit<T>; LEN]>
+where
+    S: Storage<T>,
+{
+    ring: StorageRingBuffer<T, S>,
+}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S> Iterator for IntoIter<T, LEN, S>
+where
+    S: Storage<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.ring.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.ring.len(), Some(self.ring.len()))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S> ExactSizeIterator for IntoIter<T, LEN, S> where S: Storage<T> {}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> IntoIterator for RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, LEN, S>;
+
+    fn into_iter(self) -> IntoIter<T, LEN, S> {
+        IntoIter {
+            ring: self.inner.into_inner().ring,
+        }
+    }
+}
+
+/// The error returned by `RingQueue::pop` when the queue is closed and empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Closed;
+
+impl Display for Closed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "queue is closed")
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Closed {}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> Clone for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
 where
     T: Clone,
+    M: RawMutex,
 {
     fn clone(&self) -> Self {
-        let inner = self.inner.lock().unwrap().clone();
+        let inner = self.inner.lock().clone();
+        self.with_inner(inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    /// Creates a copy of the queue, like `clone`, but copies all of the queue's storage in one go
+    /// instead of cloning one value at a time.
+    pub fn copied(&self) -> Self
+    where
+        T: Copy,
+    {
+        let inner = self.inner.lock().copied();
+        self.with_inner(inner)
+    }
+
+    // Creates a queue with the same configuration as this one which holds the values in `inner`.
+    fn with_inner(&self, inner: Inner<T, [MaybeUninit<T>; LEN]>) -> Self {
         Self {
             inner: Mutex::new(inner),
-            pop_cond: Condvar::new(),
-            push_cond: Condvar::new(),
+            pop_cond: CachePadded(RawCondvar::new()),
+            push_cond: CachePadded(RawCondvar::new()),
+            full_policy: self.full_policy,
+            on_evict: self.on_evict.clone(),
+            wait_strategy: self.wait_strategy,
+            spin_estimate: AtomicU32::new(self.spin_estimate.load(Ordering::Relaxed)),
         }
     }
 }
 
-impl<T, const LEN: usize> Default for RingQueue<T, LEN> {
+#[cfg(all(feature = "defmt", feature = "std"))]
+impl<T, const LEN: usize, S, M> defmt::Format for RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn format(&self, f: defmt::Formatter<'_>) {
+        let inner = self.inner.lock();
+        defmt::write!(
+            f,
+            "RingQueue {{ len: {=usize}, capacity: {=usize}, closed: {=bool} }}",
+            inner.len(),
+            LEN,
+            inner.closed
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> Default for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
     fn default() -> Self {
-        Self {
-            inner: Default::default(),
-            pop_cond: Default::default(),
-            push_cond: Default::default(),
+        Self::new()
+    }
+}
+
+/// Creates a queue containing the values from the iterator in order. Only the first `LEN` values are
+/// taken from the iterator; the rest are left unconsumed.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> FromIterator<T> for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut ret = Self::new();
+        let inner = ret.inner.get_mut();
+        for value in iter.into_iter().take(LEN) {
+            inner.push(value);
+        }
+        ret
+    }
+}
+
+/// Creates a full queue containing the values from the array in order.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> From<[T; LEN]> for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    fn from(values: [T; LEN]) -> Self {
+        IntoIterator::into_iter(values).collect()
+    }
+}
+
+/// Creates a queue containing the values from the `Vec` in order. Returns the `Vec` unchanged if it
+/// holds more than `LEN` values.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> TryFrom<Vec<T>> for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    type Error = Vec<T>;
+
+    fn try_from(values: Vec<T>) -> Result<Self, Vec<T>> {
+        if values.len() > LEN {
+            return Err(values);
+        }
+        Ok(values.into_iter().collect())
+    }
+}
+
+/// Creates a queue containing the values from the `VecDeque` in order. Returns the `VecDeque`
+/// unchanged if it holds more than `LEN` values.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, M> TryFrom<VecDeque<T>> for RingQueue<T, LEN, [MaybeUninit<T>; LEN], M>
+where
+    M: RawMutex,
+{
+    type Error = VecDeque<T>;
+
+    fn try_from(values: VecDeque<T>) -> Result<Self, VecDeque<T>> {
+        if values.len() > LEN {
+            return Err(values);
+        }
+        Ok(values.into_iter().collect())
+    }
+}
+
+/// Pushes every value from the iterator into the queue in order, blocking whenever the queue is full.
+/// The iterator is advanced while the queue is locked, so it must not access the queue itself. If
+/// the queue is closed, the values which could not be pushed are dropped.
+///
+/// If the queue's `FullPolicy` is not `FullPolicy::Block`, each value is instead pushed as if by
+/// `RingQueue::push` and any values which `push` hands back are dropped.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> Extend<T> for &RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        span!("extend");
+        if self.full_policy != FullPolicy::Block {
+            for value in iter {
+                let _ = self.push(value);
+            }
+            return;
+        }
+        let mut inner = self.inner.lock();
+        let mut pushed = 0;
+        for value in iter {
+            if inner.is_full() {
+                // Let consumers make room, then pick up where we left off.
+                self.notify_pushed(&mut inner, pushed);
+                pushed = 0;
+                drop(inner);
+                inner = self.lock_while(&self.push_cond, |inner| inner.is_full() && !inner.closed);
+            }
+            if inner.closed {
+                break;
+            }
+            inner.push(value);
+            pushed += 1;
         }
+        trace!("Extended queue, {} queued", inner.len());
+        self.notify_pushed(&mut inner, pushed);
     }
 }
 
 // SAFETY: This impl is safe because all accesses to `inner` -- which is the only `!Sync` field in
-//         `RingQueue` -- are done either while holding `lock` or before any reference to `self`
-//         can be available to other threads.
-unsafe impl<T, const LEN: usize> Sync for RingQueue<T, LEN> {}
+//         `RingQueue` -- are done either while holding its lock or before any reference to `self`
+//         can be available to other threads. A value pushed by one thread may be popped by
+//         another, so the values and the storage that holds them must be `Send`.
+#[cfg(feature = "std")]
+unsafe impl<T, const LEN: usize, S, M> Sync for RingQueue<T, LEN, S, M>
+where
+    T: Send,
+    S: Storage<T> + Send,
+    M: RawMutex + Sync,
+{}
+
+// A panic can't leave the queue itself broken, since user code only runs while the queue's state is
+// consistent (see `sync`). Its values are another matter: `peek_with`, `retain_mut`, `PopGuard` and
+// `pop_with_mut` hand out references to them, and since the lock is never poisoned, a value that a
+// panic left half changed would be seen by the next thread without any warning. So the queue is
+// only unwind safe when its values are.
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> UnwindSafe for RingQueue<T, LEN, S, M>
+where
+    T: UnwindSafe,
+    S: Storage<T>,
+    M: RawMutex,
+{}
+
+#[cfg(feature = "std")]
+impl<T, const LEN: usize, S, M> RefUnwindSafe for RingQueue<T, LEN, S, M>
+where
+    T: RefUnwindSafe,
+    S: Storage<T>,
+    M: RawMutex,
+{}
 
+#[cfg(feature = "std")]
 #[derive(Debug)]
-struct Inner<T, const LEN: usize> {
-    // INVARIANT: Starting at index `self.start` and wrapping around from the end of the queue to
-    //            the beginning such that `LEN` is identified with 0, the first `self.size`
-    //            elements of `self.values` are always initialized. No guarantee is made about
-    //            whether any other elements are initialized.
-    values: [MaybeUninit<T>; LEN],
-    // The index of the next value to return.
-    start: usize,
-    // The index of the next value to overwrite.
-    size: usize,
+struct Inner<T, S>
+where
+    S: Storage<T>,
+{
+    // The values in the queue.
+    ring: StorageRingBuffer<T, S>,
+    // Whether any more values may be pushed into the queue.
+    closed: bool,
+    // The tasks waiting for a value to be pushed.
+    pop_wakers: Wakers,
+    // The tasks waiting for a value to be popped.
+    push_wakers: Wakers,
+    // The number of threads blocked in `pop_when`, which have to be woken whenever the first value
+    // in the queue changes instead of only when a value is pushed.
+    front_waiters: usize,
 }
 
-impl<T, const LEN: usize> Inner<T, LEN> {
-    pub fn pop(&mut self) -> T {
-        // This method upholds the invariant on `self.values` because it reduces the size of the
-        // window covered by the invariant then shifts it so that it includes all and only those
-        // elements which were previously included and will not be returned.
-        self.size -= 1;
-        let old_start = self.start;
-        self.start = (self.start + 1) % LEN;
-        let ret = mem::replace(&mut self.values[old_start], MaybeUninit::uninit());
-        // SAFETY: This use of `clone_initialized_uninit` is safe because it is an invariant that
-        //         the first `self.size` values logically after `self.start` are initialized.
-        unsafe {
-            ret.assume_init()
+#[cfg(feature = "std")]
+impl<T, S> Inner<T, S>
+where
+    S: Storage<T>,
+{
+    pub const fn new(storage: S) -> Self {
+        Self {
+            ring: StorageRingBuffer::with_storage(storage),
+            closed: false,
+            pop_wakers: Wakers::new(),
+            push_wakers: Wakers::new(),
+            front_waiters: 0,
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    // Callers must check that the queue is not empty first.
+    pub fn pop(&mut self) -> T {
+        self.ring.pop().expect("popped from an empty queue")
+    }
+
+    // Callers must check that the queue is not full first.
     pub fn push(&mut self, value: T) {
-        // This method upholds the invariant on `self.values` because it inserts the new value as
-        // the first element after the end of the window covered by the invariant before increasing
-        // the size of that window.
-        let end = if self.size >= LEN - self.start {
-            self.size - (LEN - self.start)
-        } else {
-            self.start + self.size
-        };
-        self.values[end] = MaybeUninit::new(value);
-        self.size += 1;
+        if self.ring.push(value).is_err() {
+            panic!("pushed into a full queue");
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.ring.front()
     }
 }
 
-impl<T, const LEN: usize> Clone for Inner<T, LEN>
+#[cfg(feature = "std")]
+impl<T, const LEN: usize> Clone for Inner<T, [MaybeUninit<T>; LEN]>
 where
     T: Clone,
 {
     fn clone(&self) -> Self {
-        let mut values = MaybeUninit::uninit_array();
-        if self.size > LEN - self.start {
-            for i in (self.start..LEN).chain(0..(self.size - (LEN - self.start))) {
-                // SAFETY: This use of `clone_initialized_uninit` is safe because it is an invariant
-                //         that the first `self.size` values after `self.start` are initialized.
-                values[i] = unsafe { clone_initialized_uninit(&self.values[i]) };
-            }
-        } else {
-            for i in self.start..(self.start + self.size) {
-                // SAFETY: This use of `clone_initialized_uninit` is safe because it is an invariant
-                //         that the first `self.size` values after `self.start` are initialized.
-                values[i] = unsafe { clone_initialized_uninit(&self.values[i]) };
-            }
-        }
         Self {
-            values,
-            start: self.start,
-            size: self.size,
+            ring: self.ring.clone(),
+            closed: self.closed,
+            pop_wakers: self.pop_wakers.empty_like(),
+            push_wakers: self.push_wakers.empty_like(),
+            front_waiters: 0,
         }
     }
 }
 
-impl<T, const LEN: usize> Default for Inner<T, LEN> {
-    fn default() -> Self {
+#[cfg(feature = "std")]
+impl<T, const LEN: usize> Inner<T, [MaybeUninit<T>; LEN]> {
+    pub fn copied(&self) -> Self
+    where
+        T: Copy,
+    {
         Self {
-            values: MaybeUninit::uninit_array(),
-            start: 0,
-            size: 0,
+            ring: self.ring.copied(),
+            closed: self.closed,
+            pop_wakers: self.pop_wakers.empty_like(),
+            push_wakers: self.push_wakers.empty_like(),
+            front_waiters: 0,
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(feature = "std")]
+impl<T, const LEN: usize> Default for Inner<T, [MaybeUninit<T>; LEN]> {
+    fn default() -> Self {
+        Self::new([const { MaybeUninit::uninit() }; LEN])
+    }
+}
+
+// Takes a `pop_when` caller back out of `front_waiters` if its predicate panics. Forgotten once the
+// caller has stopped waiting, since by then it holds the lock and can do so itself.
+#[cfg(feature = "std")]
+struct FrontWaiter<'a, T, S, M>(&'a Mutex<M, Inner<T, S>>)
+where
+    S: Storage<T>,
+    M: RawMutex;
+
+#[cfg(feature = "std")]
+impl<T, S, M> Drop for FrontWaiter<'_, T, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        self.0.lock().front_waiters -= 1;
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
+    use crate::sync::Mutex;
+
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        sync::Arc,
+        thread::{self, Builder as ThreadBuilder},
+    };
 
-    use std::thread::Builder as ThreadBuilder;
+    // Counts how many times it has been dropped.
+    #[derive(Debug)]
+    struct DropCounter(Rc<Cell<usize>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
 
     #[test]
     fn it_works() {
         println!("Creating queue");
         let queue = RingQueue::<u32, 1>::new();
         println!("Pushing to queue");
-        queue.push(3);
+        queue.push(3).unwrap();
         println!("Popping from queue");
-        assert_eq!(queue.pop(), 3);
+        assert_eq!(queue.pop(), Ok(3));
+    }
+
+    #[test]
+    fn new_boxed_does_not_use_the_stack() {
+        // 40 MB is far larger than the stack of a test thread.
+        let queue = RingQueue::<[u8; 4096], 10_000>::new_boxed();
+        queue.push([1; 4096]).unwrap();
+        assert_eq!(queue.capacity(), 10_000);
+        assert_eq!(queue.pop(), Ok([1; 4096]));
+    }
+
+    #[test]
+    fn try_pop_does_not_block() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        queue.push(3).unwrap();
+        assert_eq!(queue.try_pop(), Ok(3));
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        queue.close();
+        assert_eq!(queue.try_pop(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn pop_timeout_gives_up_on_empty_queue() {
+        let queue = RingQueue::<u32, 1>::new();
+        let timeout = Duration::from_millis(10);
+        assert_eq!(queue.pop_timeout(timeout), Err(PopTimeoutError::Timeout));
+        queue.push(3).unwrap();
+        assert_eq!(queue.pop_timeout(timeout), Ok(3));
+    }
+
+    #[test]
+    fn try_push_returns_value_when_full() {
+        let queue = RingQueue::<u32, 1>::new();
+        assert_eq!(queue.try_push(3), Ok(()));
+        assert_eq!(queue.try_push(4), Err(TryPushError::Full(4)));
+        assert_eq!(queue.pop(), Ok(3));
+        assert_eq!(queue.try_push(4), Ok(()));
+    }
+
+    #[test]
+    fn push_timeout_gives_up_on_full_queue() {
+        let queue = RingQueue::<u32, 1>::new();
+        assert_eq!(queue.push_timeout(3, Duration::from_millis(10)), Ok(()));
+        let timeout = Duration::from_millis(10);
+        assert_eq!(queue.push_timeout(4, timeout), Err(PushTimeoutError::Timeout(4)));
+        assert_eq!(queue.pop(), Ok(3));
+    }
+
+    #[test]
+    fn deadlines_are_shared_between_operations() {
+        let queue = RingQueue::<u32, 1>::new();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(queue.push_until(3, deadline), Ok(()));
+        assert_eq!(queue.push_until(4, deadline), Err(PushTimeoutError::Timeout(4)));
+        assert!(Instant::now() >= deadline);
+        assert_eq!(queue.pop_until(deadline), Ok(3));
+        assert_eq!(queue.pop_until(deadline), Err(PopTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn closed_queue_drains_then_reports_closed() {
+        let queue = RingQueue::<u32, 2>::new();
+        queue.push(3).unwrap();
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.push(4), Err(PushError::Closed(4)));
+        assert_eq!(queue.try_push(4), Err(TryPushError::Closed(4)));
+        assert_eq!(queue.pop(), Ok(3));
+        assert_eq!(queue.pop(), Err(Closed));
+        assert_eq!(queue.pop_timeout(Duration::from_secs(60)), Err(PopTimeoutError::Closed));
+    }
+
+    #[test]
+    fn close_wakes_blocked_consumers() {
+        let queue = RingQueue::<u32, 2>::new();
+        thread::scope(|scope| {
+            let receiver = scope.spawn(|| {
+                let mut ret = vec![];
+                while let Ok(value) = queue.pop() {
+                    ret.push(value);
+                }
+                ret
+            });
+            queue.push(3).unwrap();
+            queue.push(4).unwrap();
+            queue.close();
+            assert_eq!(receiver.join().unwrap(), vec![3, 4]);
+        });
+    }
+
+    #[test]
+    fn iter_ends_when_queue_is_closed() {
+        let queue = RingQueue::<u32, 4>::new();
+        thread::scope(|scope| {
+            let receiver = scope.spawn(|| queue.iter().collect::<Vec<_>>());
+            for i in 0..10 {
+                queue.push(i).unwrap();
+            }
+            queue.close();
+            assert_eq!(receiver.join().unwrap(), (0..10).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn try_iter_stops_when_queue_is_empty() {
+        let queue = RingQueue::<u32, 4>::new();
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(queue.try_iter().next(), None);
+        assert!(!queue.is_closed());
+    }
+
+    #[test]
+    fn drain_takes_everything_in_order() {
+        let queue = RingQueue::<u32, 3>::new();
+        queue.push(0).unwrap();
+        assert_eq!(queue.pop(), Ok(0));
+        for i in 1..4 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.drain(), vec![1, 2, 3]);
+        assert_eq!(queue.drain(), vec![]);
+        assert_eq!(queue.try_push(4), Ok(()));
+    }
+
+    #[test]
+    fn peek_leaves_value_in_queue() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.peek(), None);
+        queue.push(3).unwrap();
+        queue.push(4).unwrap();
+        assert_eq!(queue.peek(), Some(3));
+        assert_eq!(queue.peek_with(|&value| value == 3), Some(true));
+        assert_eq!(queue.pop(), Ok(3));
+        assert_eq!(queue.peek(), Some(4));
+    }
+
+    #[test]
+    fn pop_if_only_pops_approved_values() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.pop_if(|_| panic!("called predicate on an empty queue")), None);
+        queue.push(3).unwrap();
+        queue.push(4).unwrap();
+        assert_eq!(queue.pop_if(|&value| value % 2 == 0), None);
+        assert_eq!(queue.pop_if(|&value| value == 3), Some(3));
+        assert_eq!(queue.pop_if(|&value| value % 2 == 0), Some(4));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn pop_when_waits_for_approved_value() {
+        let queue = RingQueue::<u32, 2>::new();
+        thread::scope(|scope| {
+            let control = scope.spawn(|| queue.pop_when(|&value| value == 0));
+            queue.push(1).unwrap();
+            queue.push(0).unwrap();
+            assert_eq!(queue.pop(), Ok(1));
+            assert_eq!(control.join().unwrap(), Ok(0));
+        });
+        queue.close();
+        assert_eq!(queue.pop_when(|_| false), Err(Closed));
+    }
+
+    #[test]
+    fn pop_when_stops_waiting_if_pred_panics() {
+        let queue = RingQueue::<u32, 2>::new();
+        queue.push(1).unwrap();
+        thread::scope(|scope| {
+            assert!(scope.spawn(|| queue.pop_when(|_| panic!())).join().is_err());
+        });
+        assert_eq!(queue.inner.lock().front_waiters, 0);
+        assert_eq!(queue.pop_when(|&value| value == 1), Ok(1));
+    }
+
+    #[test]
+    fn clear_wakes_blocked_producers() {
+        let queue = RingQueue::<u32, 2>::new();
+        queue.push(0).unwrap();
+        queue.push(1).unwrap();
+        thread::scope(|scope| {
+            let producers = [scope.spawn(|| queue.push(2)), scope.spawn(|| queue.push(3))];
+            queue.clear();
+            for producer in producers {
+                assert_eq!(producer.join().unwrap(), Ok(()));
+            }
+        });
+        let mut values = queue.drain();
+        values.sort_unstable();
+        assert_eq!(values, vec![2, 3]);
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn truncate_keeps_oldest_or_newest_values() {
+        let queue = RingQueue::<u32, 4>::new();
+        for i in 0..4 {
+            queue.push(i).unwrap();
+        }
+        queue.truncate(6);
+        assert_eq!(queue.len(), 4);
+        queue.truncate(3);
+        assert_eq!(queue.peek(), Some(0));
+        queue.truncate_front(1);
+        assert_eq!(queue.drain(), vec![2]);
+    }
+
+    #[test]
+    fn retain_wakes_blocked_producers() {
+        let queue = RingQueue::<u32, 3>::new();
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        thread::scope(|scope| {
+            let producer = scope.spawn(|| queue.push(3));
+            queue.retain(|&value| value != 1);
+            assert_eq!(producer.join().unwrap(), Ok(()));
+        });
+        assert_eq!(queue.drain(), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn retain_mut_updates_kept_values() {
+        let queue = RingQueue::<(u32, u32), 3>::new();
+        for i in 0..3 {
+            queue.push((i, i)).unwrap();
+        }
+        queue.retain_mut(|(_, retries)| {
+            *retries += 1;
+            *retries < 3
+        });
+        assert_eq!(queue.drain(), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn queries_search_values_in_order() {
+        let queue = RingQueue::<u32, 3>::new();
+        assert!(!queue.contains(&1));
+        for i in 1..4 {
+            queue.push(i * 2).unwrap();
+        }
+        queue.pop().unwrap();
+        queue.push(5).unwrap();
+        assert!(queue.contains(&5));
+        assert!(!queue.contains(&2));
+        assert_eq!(queue.find(|&value| value > 4), Some(6));
+        assert_eq!(queue.find(|&value| value > 6), None);
+        assert_eq!(queue.position(|&value| value % 2 == 1), Some(2));
+        assert_eq!(queue.position(|&value| value == 0), None);
+    }
+
+    #[test]
+    fn get_reads_values_by_index() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.get(0), None);
+        queue.push(3).unwrap();
+        queue.push(4).unwrap();
+        queue.pop().unwrap();
+        queue.push(5).unwrap();
+        assert_eq!((queue.get(0), queue.get(1), queue.get(2)), (Some(4), Some(5), None));
+        assert_eq!(queue.get_with(1, |&value| value * 2), Some(10));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn to_vec_leaves_values_in_queue() {
+        let queue = RingQueue::<u32, 3>::new();
+        assert_eq!(queue.to_vec(), vec![]);
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        queue.pop().unwrap();
+        queue.push(3).unwrap();
+        assert_eq!(queue.to_vec(), vec![1, 2, 3]);
+        assert_eq!(queue.drain(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn inspect_visits_values_in_order() {
+        let queue = RingQueue::<String, 2>::new();
+        queue.push("a".to_string()).unwrap();
+        queue.push("bc".to_string()).unwrap();
+        queue.pop().unwrap();
+        queue.push("def".to_string()).unwrap();
+        let mut seen = String::new();
+        queue.inspect(|value| seen.push_str(value));
+        assert_eq!(seen, "bcdef");
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn copy_to_slice_copies_as_many_values_as_fit() {
+        let queue = RingQueue::<u8, 4>::new();
+        for i in 0..4 {
+            queue.push(i).unwrap();
+        }
+        queue.pop().unwrap();
+        queue.pop().unwrap();
+        queue.push(4).unwrap();
+        let mut out = [0; 5];
+        assert_eq!(queue.copy_to_slice(&mut out), 3);
+        assert_eq!(out, [2, 3, 4, 0, 0]);
+        assert_eq!(queue.copy_to_slice(&mut out[..2]), 2);
+        assert_eq!(queue.copy_to_slice(&mut []), 0);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn slices_move_as_many_values_as_fit() {
+        let queue = RingQueue::<f32, 4>::new();
+        assert_eq!(queue.push_slice(&[1.0, 2.0, 3.0]), Ok(3));
+        let mut out = [0.0; 2];
+        assert_eq!(queue.pop_slice(&mut out), 2);
+        assert_eq!(out, [1.0, 2.0]);
+        assert_eq!(queue.push_slice(&[4.0, 5.0, 6.0, 7.0]), Ok(3));
+        let mut out = [0.0; 5];
+        assert_eq!(queue.pop_slice(&mut out), 4);
+        assert_eq!(out, [3.0, 4.0, 5.0, 6.0, 0.0]);
+        assert_eq!(queue.pop_slice(&mut out), 0);
+        queue.close();
+        assert_eq!(queue.push_slice(&[8.0]), Err(Closed));
+    }
+
+    #[test]
+    fn copied_queue_is_independent() {
+        let queue = RingQueue::<u8, 3>::new();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        let copy = queue.copied();
+        assert_eq!(copy.pop(), Ok(1));
+        assert_eq!(copy.push(3), Ok(()));
+        assert_eq!(queue.drain(), vec![1, 2]);
+        assert_eq!(copy.drain(), vec![2, 3]);
+    }
+
+    #[test]
+    fn into_iter_yields_remaining_values() {
+        let queue = RingQueue::<String, 3>::new();
+        for value in ["a", "b", "c"] {
+            queue.push(value.to_string()).unwrap();
+        }
+        queue.pop().unwrap();
+        queue.push("d".to_string()).unwrap();
+        let mut values = queue.into_iter();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.next().as_deref(), Some("b"));
+        assert_eq!(values.collect::<Vec<_>>(), vec!["c", "d"]);
+        assert_eq!(RingQueue::<u32, 2>::new().into_iter().next(), None);
+    }
+
+    #[test]
+    fn into_vec_moves_values_out_in_order() {
+        let queue = RingQueue::<String, 2>::new();
+        queue.push("a".to_string()).unwrap();
+        queue.push("b".to_string()).unwrap();
+        queue.pop().unwrap();
+        queue.push("c".to_string()).unwrap();
+        assert_eq!(queue.into_vec(), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn try_from_checks_length() {
+        let queue = RingQueue::<u32, 3>::try_from(vec![1, 2]).unwrap();
+        assert_eq!(queue.into_vec(), vec![1, 2]);
+        assert_eq!(RingQueue::<u32, 1>::try_from(vec![1, 2]).unwrap_err(), vec![1, 2]);
+        let mut values = VecDeque::from(vec![2, 3]);
+        values.push_front(1);
+        let queue = RingQueue::<u32, 3>::try_from(values).unwrap();
+        assert_eq!(queue.into_vec(), vec![1, 2, 3]);
+        let values = VecDeque::from(vec![1, 2]);
+        assert_eq!(RingQueue::<u32, 1>::try_from(values.clone()).unwrap_err(), values);
+    }
+
+    #[test]
+    fn len_tracks_occupancy() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!((queue.len(), queue.is_empty(), queue.is_full()), (0, true, false));
+        queue.push(3).unwrap();
+        assert_eq!((queue.len(), queue.is_empty(), queue.is_full()), (1, false, false));
+        queue.push(4).unwrap();
+        assert_eq!((queue.len(), queue.is_empty(), queue.is_full()), (2, false, true));
+        assert_eq!(queue.capacity(), 2);
+        assert_eq!(RingQueue::<u32, 2>::CAPACITY, 2);
+    }
+
+    #[test]
+    fn extend_blocks_until_everything_is_pushed() {
+        let queue = RingQueue::<u32, 3>::new();
+        let received = thread::scope(|scope| {
+            scope.spawn(|| (&queue).extend(0..10));
+            (0..10).map(|_| queue.pop().unwrap()).collect::<Vec<_>>()
+        });
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn queue_can_be_built_from_values() {
+        let queue = RingQueue::<u32, 3>::from([1, 2, 3]);
+        assert!(queue.is_full());
+        assert_eq!(queue.drain(), vec![1, 2, 3]);
+        let mut values = 0..5;
+        let queue = (&mut values).collect::<RingQueue<u32, 3>>();
+        assert_eq!(queue.drain(), vec![0, 1, 2]);
+        assert_eq!(values.next(), Some(3));
+    }
+
+    #[test]
+    fn push_all_keeps_batches_contiguous() {
+        let queue = RingQueue::<u32, 4>::new();
+        let received = thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..5 {
+                    queue.push_all(vec![i * 3, i * 3 + 1, i * 3 + 2]).unwrap();
+                }
+            });
+            scope.spawn(|| {
+                for i in 100..115 {
+                    queue.push(i).unwrap();
+                }
+            });
+            (0..30).map(|_| queue.pop().unwrap()).collect::<Vec<_>>()
+        });
+        for i in 0..5 {
+            let start = received.iter().position(|&x| x == i * 3).unwrap();
+            assert_eq!(received[start..(start + 3)], [i * 3, i * 3 + 1, i * 3 + 2]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_all_rejects_oversized_batches() {
+        let queue = RingQueue::<u32, 2>::new();
+        let _ = queue.push_all(vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn pop_many_waits_for_every_value() {
+        let queue = RingQueue::<u32, 3>::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..10 {
+                    queue.push(i).unwrap();
+                }
+                queue.close();
+            });
+            assert_eq!(queue.pop_many(7), (0..7).collect::<Vec<_>>());
+            assert_eq!(queue.pop_many(7), (7..10).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn pop_up_to_takes_only_available_values() {
+        let queue = RingQueue::<u32, 4>::new();
+        assert_eq!(queue.pop_up_to(2), vec![]);
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.pop_up_to(2), vec![0, 1]);
+        assert_eq!(queue.pop_up_to(2), vec![2]);
+    }
+
+    #[test]
+    fn try_push_all_is_all_or_nothing() {
+        let queue = RingQueue::<u32, 4>::new();
+        assert_eq!(queue.try_push_all(vec![1, 2, 3]), Ok(()));
+        assert_eq!(queue.try_push_all(vec![4, 5]), Err(vec![4, 5]));
+        assert_eq!(queue.try_push_all(vec![4]), Ok(()));
+        assert_eq!(queue.drain(), vec![1, 2, 3, 4]);
+        assert_eq!(queue.try_push_all(0..5), Err(vec![0, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn force_push_evicts_oldest_value() {
+        let queue = RingQueue::<u32, 2>::new();
+        assert_eq!(queue.force_push(1), None);
+        assert_eq!(queue.force_push(2), None);
+        assert_eq!(queue.force_push(3), Some(1));
+        assert_eq!(queue.drain(), vec![2, 3]);
+        queue.close();
+        assert_eq!(queue.force_push(4), Some(4));
+    }
+
+    #[test]
+    fn full_policy_governs_push_into_full_queue() {
+        let queue = RingQueue::<u32, 2>::builder().full_policy(FullPolicy::DropNewest).build();
+        (&queue).extend(0..2);
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.drain(), vec![0, 1]);
+        let queue = RingQueue::<u32, 2>::builder().full_policy(FullPolicy::DropOldest).build();
+        (&queue).extend(0..2);
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.drain(), vec![1, 2]);
+        let queue = RingQueue::<u32, 2>::builder().full_policy(FullPolicy::Reject).build();
+        (&queue).extend(0..2);
+        assert_eq!(queue.push(2), Err(PushError::Full(2)));
+        assert_eq!(queue.drain(), vec![0, 1]);
+    }
+
+    #[test]
+    fn spinning_queue_still_parks() {
+        let queue = Arc::new(RingQueue::<u32, 1>::builder().spin(100).build());
+        let consumer = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || (0..100).map(|_| queue.pop().unwrap()).collect::<Vec<_>>())
+        };
+        // The consumer gives up spinning long before the first value arrives.
+        thread::sleep(Duration::from_millis(50));
+        for i in 0..100 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+        let late = queue.pop_timeout(Duration::from_millis(10));
+        assert_eq!(late, Err(PopTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn every_wait_strategy_hands_off_values() {
+        for wait_strategy in [
+            WaitStrategy::BusySpin,
+            WaitStrategy::Yield,
+            WaitStrategy::SpinThenPark(10),
+            WaitStrategy::Adaptive,
+            WaitStrategy::Park,
+        ] {
+            let queue = RingQueue::<u32, 2>::builder().wait_strategy(wait_strategy).build();
+            let queue = Arc::new(queue);
+            let consumer = {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || (0..100).map(|_| queue.pop().unwrap()).collect::<Vec<_>>())
+            };
+            for i in 0..100 {
+                queue.push(i).unwrap();
+            }
+            let received = consumer.join().unwrap();
+            assert_eq!(received, (0..100).collect::<Vec<_>>(), "{:?}", wait_strategy);
+            let late = queue.pop_timeout(Duration::from_millis(10));
+            assert_eq!(late, Err(PopTimeoutError::Timeout), "{:?}", wait_strategy);
+        }
+    }
+
+    #[test]
+    fn adaptive_spinning_follows_recent_waits() {
+        let queue = RingQueue::<u32, 1>::builder().wait_strategy(WaitStrategy::Adaptive).build();
+        for _ in 0..100 {
+            queue.adapt_spin_estimate(500);
+        }
+        assert!((400..=500).contains(&queue.spin_estimate.load(Ordering::Relaxed)));
+        for _ in 0..100 {
+            queue.adapt_spin_estimate(u32::MAX);
+        }
+        assert!(queue.spin_estimate.load(Ordering::Relaxed) <= MAX_ADAPTIVE_SPINS);
+        // Waiting for longer than it spins makes it spin less.
+        let before = queue.spin_estimate.load(Ordering::Relaxed);
+        let late = queue.pop_timeout(Duration::from_millis(1));
+        assert_eq!(late, Err(PopTimeoutError::Timeout));
+        assert!(queue.spin_estimate.load(Ordering::Relaxed) < before);
+    }
+
+    #[test]
+    fn user_supplied_mutex_locks_queue() {
+        static LOCKS: AtomicU32 = AtomicU32::new(0);
+
+        // Counts how many times any mutex of this type is locked.
+        struct CountingRawMutex(parking_lot::RawMutex);
+
+        // SAFETY: This impl is safe because `parking_lot::RawMutex` does all of the locking.
+        unsafe impl RawMutex for CountingRawMutex {
+            const INIT: Self = Self(parking_lot::RawMutex::INIT);
+
+            type GuardMarker = lock_api::GuardNoSend;
+
+            fn lock(&self) {
+                LOCKS.fetch_add(1, Ordering::Relaxed);
+                self.0.lock();
+            }
+
+            fn try_lock(&self) -> bool {
+                LOCKS.fetch_add(1, Ordering::Relaxed);
+                self.0.try_lock()
+            }
+
+            unsafe fn unlock(&self) {
+                self.0.unlock();
+            }
+        }
+
+        let queue = RingQueue::<u32, 1>::builder()
+            .raw_mutex::<CountingRawMutex>()
+            .build();
+        thread::scope(|scope| {
+            let receiver = scope.spawn(|| queue.iter().collect::<Vec<_>>());
+            for i in 0..100 {
+                queue.push(i).unwrap();
+            }
+            queue.close();
+            assert_eq!(receiver.join().unwrap(), (0..100).collect::<Vec<_>>());
+        });
+        assert!(LOCKS.load(Ordering::Relaxed) >= 200);
+    }
+
+    #[test]
+    fn evicted_values_are_given_to_callback() {
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let queue = {
+            let evicted = Arc::clone(&evicted);
+            RingQueue::<u32, 2>::builder()
+                .full_policy(FullPolicy::DropOldest)
+                .on_evict(move |value| evicted.lock().push(value))
+                .build()
+        };
+        (&queue).extend(0..5);
+        assert_eq!(queue.drain(), vec![3, 4]);
+        assert_eq!(*evicted.lock(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn drop_oldest_evicts_pushed_value_without_capacity() {
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let queue = {
+            let evicted = Arc::clone(&evicted);
+            RingQueue::<u32, 0>::builder()
+                .full_policy(FullPolicy::DropOldest)
+                .on_evict(move |value| evicted.lock().push(value))
+                .build()
+        };
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert!(queue.is_empty());
+        assert_eq!(*evicted.lock(), vec![1, 2]);
+    }
+
+    #[test]
+    fn panic_while_locked_does_not_break_queue() {
+        #[derive(Debug, PartialEq)]
+        struct PanicOnClone(u32);
+
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                panic!("cannot clone {}", self.0);
+            }
+        }
+
+        let queue = Arc::new(RingQueue::<PanicOnClone, 2>::new());
+        queue.push(PanicOnClone(1)).unwrap();
+        let cloner = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || queue.as_ref().clone())
+        };
+        assert!(cloner.join().is_err());
+        queue.push(PanicOnClone(2)).unwrap();
+        assert_eq!(queue.pop(), Ok(PanicOnClone(1)));
+        assert_eq!(queue.pop(), Ok(PanicOnClone(2)));
+    }
+
+    #[test]
+    fn queue_can_be_used_after_catching_a_panic() {
+        let queue = RingQueue::<u32, 2>::new();
+        let result = std::panic::catch_unwind(|| {
+            queue.push(1).unwrap();
+            panic!("worker failed");
+        });
+        assert!(result.is_err());
+        queue.push(2).unwrap();
+        assert_eq!(queue.drain(), vec![1, 2]);
+    }
+
+    #[test]
+    fn dropping_queue_drops_remaining_values() {
+        let drops = Rc::new(Cell::new(0));
+        let queue = RingQueue::<_, 3>::new();
+        for _ in 0..3 {
+            queue.push(DropCounter(Rc::clone(&drops))).unwrap();
+        }
+        drop(queue.pop().unwrap());
+        drop(queue.pop().unwrap());
+        assert_eq!(drops.get(), 2);
+        // Wrap the remaining values around the end of the buffer.
+        queue.push(DropCounter(Rc::clone(&drops))).unwrap();
+        queue.push(DropCounter(Rc::clone(&drops))).unwrap();
+        drop(queue);
+        assert_eq!(drops.get(), 5);
     }
 
     #[test]
@@ -194,7 +2153,7 @@ mod tests {
         let sender = unsafe {
             ThreadBuilder::new().name("Sender".into()).spawn_unchecked(|| {
                 for i in 0..10 {
-                    queue.push(i);
+                    queue.push(i).unwrap();
                 }
             }).unwrap()
         };
@@ -204,9 +2163,9 @@ mod tests {
             ThreadBuilder::new().name("Receiver".into()).spawn_unchecked(|| {
                 let mut ret = vec![];
                 for _ in 0..10 {
-                    ret.push(queue.pop());
+                    ret.push(queue.pop().unwrap());
                 }
-                return ret;
+                ret
             }).unwrap()
         };
         sender.join().unwrap();
@@ -222,7 +2181,7 @@ mod tests {
         let sender1 = unsafe {
             ThreadBuilder::new().name("Sender1".into()).spawn_unchecked(|| {
                 for i in 0..10 {
-                    queue.push(i);
+                    queue.push(i).unwrap();
                 }
             }).unwrap()
         };
@@ -231,7 +2190,7 @@ mod tests {
         let sender2 = unsafe {
             ThreadBuilder::new().name("Sender2".into()).spawn_unchecked(|| {
                 for i in 10..20 {
-                    queue.push(i);
+                    queue.push(i).unwrap();
                 }
             }).unwrap()
         };
@@ -241,9 +2200,9 @@ mod tests {
             ThreadBuilder::new().name("Receiver".into()).spawn_unchecked(|| {
                 let mut ret = vec![];
                 for _ in 0..20 {
-                    ret.push(queue.pop());
+                    ret.push(queue.pop().unwrap());
                 }
-                return ret;
+                ret
             }).unwrap()
         };
         sender1.join().unwrap();
@@ -0,0 +1,334 @@
+//! Several operations on a `RingQueue` as one atomic step, either applied immediately or staged
+//! in a transaction.
+
+use crate::{DefaultRawMutex, Inner, MutexGuard, PopError, RingQueue, Storage, TryPushError};
+
+use lock_api::RawMutex;
+
+use std::mem::MaybeUninit;
+
+/// A `RingQueue` which is locked for the duration of a call to `RingQueue::with_lock`. Its
+/// operations never block, and no other thread can see the queue between them.
+#[derive(Debug)]
+pub struct LockedQueue<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+    // The number of values pushed so far, so that waiting consumers can be woken once the queue is
+    // unlocked.
+    pushed: usize,
+    // Whether any values have been popped so far.
+    popped: bool,
+}
+
+impl<T, const LEN: usize, S, M> LockedQueue<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed
+    }
+
+    /// Gets a reference to the first value in the queue. Returns `None` if the queue is empty.
+    pub fn peek(&self) -> Option<&T> {
+        self.inner.front()
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value in
+    /// `TryPushError::Full` if the queue is full, regardless of the queue's `FullPolicy`, or in
+    /// `TryPushError::Closed` if the queue is closed.
+    pub fn push(&mut self, value: T) -> Result<(), TryPushError<T>> {
+        if self.inner.closed {
+            return Err(TryPushError::Closed(value));
+        }
+        if self.inner.is_full() {
+            return Err(TryPushError::Full(value));
+        }
+        self.inner.push(value);
+        self.pushed += 1;
+        Ok(())
+    }
+
+    /// Gets the first value out of the queue if there is one. Returns `PopError::Empty` if the
+    /// queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        if self.inner.len() == 0 {
+            return Err(if self.inner.closed {
+                PopError::Closed
+            } else {
+                PopError::Empty
+            });
+        }
+        self.popped = true;
+        Ok(self.inner.pop())
+    }
+}
+
+impl<T, const LEN: usize, S, M> Drop for LockedQueue<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        // Waking waiters here instead of after each operation means that they are woken even if
+        // the closure given to `with_lock` panics, and that they are only woken once.
+        if self.pushed > 0 {
+            self.queue.notify_pushed(&mut self.inner, self.pushed);
+        }
+        if self.popped {
+            self.queue.notify_popped(&mut self.inner);
+        }
+    }
+}
+
+/// A series of pushes and pops on a `RingQueue` which are staged until they are all committed at
+/// once. Created by `RingQueue::transaction`.
+///
+/// The queue stays locked for as long as the transaction lives, so no other thread can see the
+/// queue until the transaction has been committed or rolled back. Dropping the transaction rolls it
+/// back.
+#[derive(Debug)]
+#[must_use = "a transaction does nothing unless it is committed"]
+pub struct Transaction<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+    // The number of values staged to be popped. They are the first values in the queue followed,
+    // if there are more of them than there are values in the queue, by the first staged pushes.
+    popped: usize,
+    // The values staged to be pushed, in order.
+    pushed: Vec<T>,
+}
+
+impl<T, const LEN: usize, S, M> Transaction<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// The number of values that will be in the queue if the transaction is committed.
+    pub fn len(&self) -> usize {
+        self.inner.len() + self.pushed.len() - self.popped
+    }
+
+    /// Whether the queue will contain no values if the transaction is committed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets a reference to the first value that will be in the queue if the transaction is
+    /// committed. Returns `None` if there will be no values in the queue.
+    pub fn peek(&self) -> Option<&T> {
+        self.staged(self.popped)
+    }
+
+    /// Stages `value` to be added to the end of the queue. Returns the value in
+    /// `TryPushError::Full` if the queue will already be full, regardless of the queue's
+    /// `FullPolicy`, or in `TryPushError::Closed` if the queue is closed.
+    pub fn push(&mut self, value: T) -> Result<(), TryPushError<T>> {
+        if self.inner.closed {
+            return Err(TryPushError::Closed(value));
+        }
+        if self.len() == LEN {
+            return Err(TryPushError::Full(value));
+        }
+        self.pushed.push(value);
+        Ok(())
+    }
+
+    /// Stages the first value that will be in the queue to be removed, returning a reference to it.
+    /// The value itself is returned by `commit`. Returns `PopError::Empty` if there will be no
+    /// values in the queue, or `PopError::Closed` if the queue is also closed.
+    pub fn pop(&mut self) -> Result<&T, PopError> {
+        let index = self.popped;
+        if index == self.inner.len() + self.pushed.len() {
+            return Err(if self.inner.closed {
+                PopError::Closed
+            } else {
+                PopError::Empty
+            });
+        }
+        self.popped += 1;
+        Ok(self.staged(index).expect("checked that the value exists"))
+    }
+
+    /// Applies every staged push and pop to the queue at once. Returns the popped values in the
+    /// order that they were popped.
+    pub fn commit(mut self) -> Vec<T> {
+        let from_queue = self.popped.min(self.inner.len());
+        let mut ret = (0..from_queue).map(|_| self.inner.pop()).collect::<Vec<_>>();
+        ret.extend(self.pushed.drain(..self.popped - from_queue));
+        let pushed = self.pushed.len();
+        for value in self.pushed.drain(..) {
+            self.inner.push(value);
+        }
+        trace!("Committed transaction, {} queued", self.inner.len());
+        if pushed > 0 {
+            self.queue.notify_pushed(&mut self.inner, pushed);
+        }
+        if from_queue > 0 {
+            self.queue.notify_popped(&mut self.inner);
+        }
+        ret
+    }
+
+    /// Discards every staged push and pop, leaving the queue unchanged. Returns the values which
+    /// were staged to be pushed.
+    pub fn rollback(mut self) -> Vec<T> {
+        self.pushed.drain(..).collect()
+    }
+
+    // Gets the value which will be `index` values from the front of the queue if nothing is popped.
+    fn staged(&self, index: usize) -> Option<&T> {
+        match index.checked_sub(self.inner.len()) {
+            None => self.inner.ring.get(index),
+            Some(index) => self.pushed.get(index),
+        }
+    }
+}
+
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Locks the queue and starts a transaction on it, in which pushes and pops are staged until
+    /// they are committed all at once. No other thread can see the queue until the transaction has
+    /// been committed or rolled back.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 4>::new();
+    /// queue.push(1).unwrap();
+    /// let mut transaction = queue.transaction();
+    /// assert_eq!(transaction.pop(), Ok(&1));
+    /// transaction.push(2).unwrap();
+    /// assert_eq!(transaction.rollback(), vec![2]);
+    /// assert_eq!(queue.peek(), Some(1));
+    ///
+    /// let mut transaction = queue.transaction();
+    /// transaction.pop().unwrap();
+    /// transaction.push(2).unwrap();
+    /// assert_eq!(transaction.commit(), vec![1]);
+    /// assert_eq!(queue.drain(), vec![2]);
+    /// ```
+    pub fn transaction(&self) -> Transaction<'_, T, LEN, S, M> {
+        span!("transaction");
+        Transaction {
+            queue: self,
+            inner: self.inner.lock(),
+            popped: 0,
+            pushed: vec![],
+        }
+    }
+
+    /// Locks the queue and calls `f` on it, so that every operation `f` performs on the queue
+    /// happens as one atomic step. Threads waiting to push or pop are woken once `f` returns.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 4>::new();
+    /// queue.push(1).unwrap();
+    /// // No other thread can see the queue without the first value or with only one of its
+    /// // replacements.
+    /// queue.with_lock(|queue| {
+    ///     let value = queue.pop().unwrap();
+    ///     queue.push(value * 2).unwrap();
+    ///     queue.push(value * 3).unwrap();
+    /// });
+    /// assert_eq!(queue.drain(), vec![2, 3]);
+    /// ```
+    pub fn with_lock<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut LockedQueue<'_, T, LEN, S, M>) -> R,
+    {
+        span!("with_lock");
+        let mut locked = LockedQueue {
+            queue: self,
+            inner: self.inner.lock(),
+            pushed: 0,
+            popped: false,
+        };
+        f(&mut locked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn transactions_pop_staged_pushes_in_order() {
+        let queue = RingQueue::<u32, 3>::new();
+        queue.push(1).unwrap();
+        let mut transaction = queue.transaction();
+        assert_eq!(transaction.push(2), Ok(()));
+        assert_eq!(transaction.push(3), Ok(()));
+        assert_eq!(transaction.push(4), Err(TryPushError::Full(4)));
+        assert_eq!(transaction.pop(), Ok(&1));
+        assert_eq!(transaction.pop(), Ok(&2));
+        assert_eq!(transaction.peek(), Some(&3));
+        assert_eq!(transaction.push(4), Ok(()));
+        assert_eq!(transaction.len(), 2);
+        assert_eq!(transaction.commit(), vec![1, 2]);
+        assert_eq!(queue.drain(), vec![3, 4]);
+        let mut transaction = queue.transaction();
+        assert_eq!(transaction.pop(), Err(PopError::Empty));
+        transaction.push(5).unwrap();
+        drop(transaction);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn with_lock_wakes_waiting_threads() {
+        let queue = RingQueue::<u32, 2>::new();
+        thread::scope(|scope| {
+            let consumer = scope.spawn(|| (queue.pop(), queue.pop()));
+            queue.with_lock(|queue| {
+                assert_eq!(queue.pop(), Err(PopError::Empty));
+                assert_eq!(queue.push(1), Ok(()));
+                assert_eq!(queue.peek(), Some(&1));
+                assert_eq!(queue.push(2), Ok(()));
+                assert_eq!(queue.push(3), Err(TryPushError::Full(3)));
+            });
+            assert_eq!(consumer.join().unwrap(), (Ok(1), Ok(2)));
+        });
+        queue.close();
+        queue.with_lock(|queue| {
+            assert!(queue.is_closed());
+            assert_eq!(queue.push(3), Err(TryPushError::Closed(3)));
+            assert_eq!(queue.pop(), Err(PopError::Closed));
+        });
+    }
+}
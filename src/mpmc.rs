@@ -0,0 +1,469 @@
+//! A lock-free queue for any number of producers and consumers.
+
+use crate::{padding::CachePadded, PopError, TryPushError};
+#[cfg(feature = "std")]
+use crate::{
+    sync::{Condvar, Mutex},
+    Closed, PushError,
+};
+
+use core::{
+    cell::UnsafeCell,
+    hint,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+#[cfg(feature = "std")]
+use std::sync::atomic;
+
+/// A fixed-size queue which any number of threads may push into and pop from at once without
+/// taking a lock. Each element has its own sequence number which says whether it is ready to be
+/// written or read, as in Dmitry Vyukov's bounded MPMC queue. Its operations return the same errors
+/// as `crate::RingQueue`'s, and it can be closed in the same way. With the `std` feature, `push`
+/// and `pop` block while the queue is full or empty and open.
+#[derive(Debug)]
+pub struct RingQueue<T, const LEN: usize> {
+    slots: [Slot<T>; LEN],
+    // The position of the next value to be claimed for pushing. Each counter has its own cache
+    // line so that producers and consumers don't invalidate each other's.
+    //
+    // A position is the index of a slot in its low bits and the number of times that the queue has
+    // been lapped in the bits above them, as in `crossbeam`'s `ArrayQueue`. Counting values instead
+    // would move the counters to a different slot whenever they wrap around at `usize::MAX` and
+    // `LEN` is not a power of two. The tail also has `MARK_BIT` set once the queue is closed, so
+    // that no producer can claim a position after that.
+    tail: CachePadded<AtomicUsize>,
+    // The position of the next value to be claimed for popping.
+    head: CachePadded<AtomicUsize>,
+    // The threads waiting for room.
+    #[cfg(feature = "std")]
+    push_waiters: Waiters,
+    // The threads waiting for a value.
+    #[cfg(feature = "std")]
+    pop_waiters: Waiters,
+}
+
+// One element of a `RingQueue`.
+#[derive(Debug)]
+struct Slot<T> {
+    // INVARIANT: For the use of this slot as the element at position `pos`, `sequence` is `pos`
+    //            while `value` is uninitialized and waiting to be written, then `pos + 1` once
+    //            `value` is initialized and waiting to be read. Reading the value sets it to
+    //            `pos + ONE_LAP`, the position at which the slot will next be written.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T, const LEN: usize> RingQueue<T, LEN> {
+    // The bit of the tail which is set once the queue is closed. Always greater than `LEN`, so that
+    // the index bits of `pos + 1` never carry into it.
+    const MARK_BIT: usize = (LEN + 1).next_power_of_two();
+
+    // The amount that a position goes up by each time the queue is lapped.
+    const ONE_LAP: usize = Self::MARK_BIT * 2;
+
+    /// Create a new, empty `RingQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of values currently in the queue. Other threads may change it at any time, so
+    /// it is only a hint.
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst) & !Self::MARK_BIT;
+            let head = self.head.load(Ordering::SeqCst);
+            // Only use the two positions if the tail didn't move while the head was loaded.
+            if self.tail.load(Ordering::SeqCst) & !Self::MARK_BIT != tail {
+                continue;
+            }
+            let (head_index, tail_index) = (Self::index(head), Self::index(tail));
+            return if head_index < tail_index {
+                tail_index - head_index
+            } else if head_index > tail_index {
+                LEN - head_index + tail_index
+            } else if head == tail {
+                0
+            } else {
+                LEN
+            };
+        }
+    }
+
+    /// Whether the queue currently contains no values. Like `len`, this is only a hint.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value
+    /// immediately if the queue is full or closed.
+    pub fn try_push(&self, value: T) -> Result<(), TryPushError<T>> {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            if tail & Self::MARK_BIT != 0 {
+                return Err(TryPushError::Closed(value));
+            } else if LEN == 0 {
+                return Err(TryPushError::Full(value));
+            }
+            let slot = &self.slots[Self::index(tail)];
+            let lag = slot.sequence.load(Ordering::Acquire).wrapping_sub(tail) as isize;
+            if lag < 0 {
+                // The slot still holds the value from `LEN` positions ago.
+                return Err(TryPushError::Full(value));
+            } else if lag > 0 {
+                // Another producer claimed this position first.
+                tail = self.tail.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.tail.compare_exchange_weak(
+                tail,
+                Self::next(tail),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // SAFETY: This write is safe because claiming `tail` gives this thread
+                    //         exclusive access to the slot until its sequence number is advanced.
+                    unsafe {
+                        (*slot.value.get()).write(value);
+                    }
+                    slot.sequence.store(tail.wrapping_add(1), Ordering::Release);
+                    #[cfg(feature = "std")]
+                    self.pop_waiters.notify();
+                    return Ok(());
+                }
+                Err(current) => tail = current,
+            }
+        }
+    }
+
+    /// Gets the first value out of the queue if there is one. Returns immediately if the queue is
+    /// empty, with `PopError::Closed` if it has also been closed.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        if LEN == 0 {
+            return Err(self.empty_error());
+        }
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[Self::index(head)];
+            let lag = slot
+                .sequence
+                .load(Ordering::Acquire)
+                .wrapping_sub(head.wrapping_add(1)) as isize;
+            if lag < 0 {
+                // Nothing has been pushed at this position yet.
+                let tail = self.tail.load(Ordering::SeqCst);
+                if tail & Self::MARK_BIT == 0 || tail & !Self::MARK_BIT == head {
+                    return Err(self.empty_error());
+                }
+                // A producer claimed this position before the queue was closed and is still
+                // writing its value, which has to be popped before the queue counts as closed.
+                hint::spin_loop();
+                head = self.head.load(Ordering::Relaxed);
+                continue;
+            } else if lag > 0 {
+                // Another consumer claimed this position first.
+                head = self.head.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.head.compare_exchange_weak(
+                head,
+                Self::next(head),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // SAFETY: This read is safe because the sequence number shows that the value
+                    //         is initialized, and claiming `head` means no other thread reads it.
+                    let value = unsafe { (*slot.value.get()).assume_init_read() };
+                    slot.sequence.store(head.wrapping_add(Self::ONE_LAP), Ordering::Release);
+                    #[cfg(feature = "std")]
+                    self.push_waiters.notify();
+                    return Ok(value);
+                }
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Adds a new value to the end of the queue, blocking while the queue is full. Returns the
+    /// value in `PushError::Closed` if the queue is closed. Never returns `PushError::Full`.
+    #[cfg(feature = "std")]
+    pub fn push(&self, mut value: T) -> Result<(), PushError<T>> {
+        loop {
+            match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(TryPushError::Closed(value)) => return Err(PushError::Closed(value)),
+                Err(TryPushError::Full(returned)) => value = returned,
+            }
+            self.push_waiters.wait_while(|| self.is_full_at_tail() && !self.is_closed());
+        }
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and every value in it has been popped.
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> Result<T, Closed> {
+        loop {
+            match self.try_pop() {
+                Ok(value) => return Ok(value),
+                Err(PopError::Closed) => return Err(Closed),
+                Err(PopError::Empty) => {}
+            }
+            self.pop_waiters.wait_while(|| self.is_empty_at_head() && !self.is_closed());
+        }
+    }
+
+    /// Closes the queue. After this, every push fails, and pops fail once the values already in
+    /// the queue have been popped. Wakes every thread blocked in `push` or `pop`.
+    pub fn close(&self) {
+        if self.tail.fetch_or(Self::MARK_BIT, Ordering::SeqCst) & Self::MARK_BIT == 0 {
+            #[cfg(feature = "std")]
+            {
+                self.push_waiters.notify();
+                self.pop_waiters.notify();
+            }
+        }
+    }
+
+    /// Whether the queue has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.tail.load(Ordering::SeqCst) & Self::MARK_BIT != 0
+    }
+
+    // The error for popping from an empty queue.
+    fn empty_error(&self) -> PopError {
+        if self.is_closed() {
+            PopError::Closed
+        } else {
+            PopError::Empty
+        }
+    }
+
+    // Whether the slot for the next push still holds an unpopped value.
+    #[cfg(feature = "std")]
+    fn is_full_at_tail(&self) -> bool {
+        if LEN == 0 {
+            return true;
+        }
+        let tail = self.tail.load(Ordering::Relaxed) & !Self::MARK_BIT;
+        let slot = &self.slots[Self::index(tail)];
+        (slot.sequence.load(Ordering::Acquire).wrapping_sub(tail) as isize) < 0
+    }
+
+    // Whether the slot for the next pop has not been pushed into yet.
+    #[cfg(feature = "std")]
+    fn is_empty_at_head(&self) -> bool {
+        if LEN == 0 {
+            return true;
+        }
+        let head = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[Self::index(head)];
+        (slot.sequence.load(Ordering::Acquire).wrapping_sub(head.wrapping_add(1)) as isize) < 0
+    }
+
+    // The index of the slot at `pos`.
+    const fn index(pos: usize) -> usize {
+        pos & (Self::MARK_BIT - 1)
+    }
+
+    // The position after `pos`, which moves on to the first slot of the next lap after the last
+    // slot.
+    const fn next(pos: usize) -> usize {
+        if Self::index(pos) + 1 < LEN {
+            pos + 1
+        } else {
+            (pos & !(Self::ONE_LAP - 1)).wrapping_add(Self::ONE_LAP)
+        }
+    }
+}
+
+impl<T, const LEN: usize> Default for RingQueue<T, LEN> {
+    fn default() -> Self {
+        let mut index = 0;
+        Self {
+            slots: [(); LEN].map(|_| {
+                index += 1;
+                Slot {
+                    sequence: AtomicUsize::new(index - 1),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            }),
+            tail: CachePadded(AtomicUsize::new(0)),
+            head: CachePadded(AtomicUsize::new(0)),
+            #[cfg(feature = "std")]
+            push_waiters: Waiters::default(),
+            #[cfg(feature = "std")]
+            pop_waiters: Waiters::default(),
+        }
+    }
+}
+
+impl<T, const LEN: usize> Drop for RingQueue<T, LEN> {
+    fn drop(&mut self) {
+        // Popping every remaining value drops exactly the elements whose sequence numbers show
+        // that they are initialized.
+        while self.try_pop().is_ok() {}
+    }
+}
+
+// SAFETY: This impl is safe because the sequence number of each slot gives exactly one thread at a
+//         time access to its value, and every value that is moved into the queue by one thread
+//         may be moved out by another.
+unsafe impl<T, const LEN: usize> Sync for RingQueue<T, LEN> where T: Send {}
+
+// The threads blocked on one side of a queue. The mutex is only locked by threads which are about
+// to wait or which have to wake a waiting thread.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct Waiters {
+    // The number of threads which are waiting or about to wait.
+    count: AtomicUsize,
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+#[cfg(feature = "std")]
+impl Waiters {
+    // Blocks for as long as `condition` holds.
+    fn wait_while<F>(&self, mut condition: F)
+    where
+        F: FnMut() -> bool,
+    {
+        let mut guard = self.lock.lock();
+        self.count.fetch_add(1, Ordering::SeqCst);
+        // Either this check sees the other side's progress or the other side sees `count`.
+        atomic::fence(Ordering::SeqCst);
+        while condition() {
+            guard = self.cond.wait(guard);
+        }
+        self.count.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Wakes every waiting thread. The caller must have made its progress visible first.
+    fn notify(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.count.load(Ordering::Relaxed) > 0 {
+            let _guard = self.lock.lock();
+            self.cond.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_operations_wrap_around() {
+        let queue = RingQueue::<u32, 2>::new();
+        for i in 0..10 {
+            assert_eq!(queue.try_push(i), Ok(()));
+            assert_eq!(queue.try_push(i + 100), Ok(()));
+            assert_eq!(queue.try_push(i + 200), Err(TryPushError::Full(i + 200)));
+            assert_eq!(queue.len(), 2);
+            assert_eq!(queue.try_pop(), Ok(i));
+            assert_eq!(queue.try_pop(), Ok(i + 100));
+            assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        }
+    }
+
+    #[test]
+    fn positions_wrap_around_usize() {
+        let queue = RingQueue::<u32, 3>::new();
+        // Start on the last lap before the positions wrap around.
+        let last_lap = usize::MAX & !(RingQueue::<u32, 3>::ONE_LAP - 1);
+        queue.tail.store(last_lap, Ordering::Relaxed);
+        queue.head.store(last_lap, Ordering::Relaxed);
+        for (i, slot) in queue.slots.iter().enumerate() {
+            slot.sequence.store(last_lap + i, Ordering::Relaxed);
+        }
+        for i in 0..10 {
+            assert_eq!(queue.try_push(i), Ok(()));
+            assert_eq!(queue.try_push(i + 100), Ok(()));
+            assert_eq!(queue.len(), 2);
+            assert_eq!(queue.try_pop(), Ok(i));
+            assert_eq!(queue.try_pop(), Ok(i + 100));
+            assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        }
+        for i in 0..3 {
+            assert_eq!(queue.try_push(i), Ok(()));
+        }
+        assert_eq!(queue.try_push(3), Err(TryPushError::Full(3)));
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn zero_capacity_is_always_full_and_empty() {
+        let queue = RingQueue::<u32, 0>::new();
+        assert_eq!(queue.try_push(0), Err(TryPushError::Full(0)));
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        assert!(queue.is_full_at_tail());
+        assert!(queue.is_empty_at_head());
+        assert_eq!(queue.len(), 0);
+        queue.close();
+        assert_eq!(queue.push(1), Err(PushError::Closed(1)));
+        assert_eq!(queue.pop(), Err(Closed));
+    }
+
+    #[test]
+    fn closed_queue_hands_out_remaining_values() {
+        let queue = RingQueue::<u32, 2>::new();
+        queue.try_push(1).unwrap();
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.try_push(2), Err(TryPushError::Closed(2)));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.try_pop(), Ok(1));
+        assert_eq!(queue.try_pop(), Err(PopError::Closed));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn close_wakes_blocked_threads() {
+        let full = RingQueue::<u32, 1>::new();
+        full.push(1).unwrap();
+        let empty = RingQueue::<u32, 1>::new();
+        std::thread::scope(|scope| {
+            let producer = scope.spawn(|| full.push(2));
+            let consumer = scope.spawn(|| empty.pop());
+            full.close();
+            empty.close();
+            assert_eq!(producer.join().unwrap(), Err(PushError::Closed(2)));
+            assert_eq!(consumer.join().unwrap(), Err(Closed));
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn many_producers_and_consumers() {
+        let queue = RingQueue::<u32, 4>::new();
+        let mut received = std::thread::scope(|scope| {
+            for n in 0..4 {
+                let queue = &queue;
+                scope.spawn(move || {
+                    for i in (n * 1000)..(n * 1000 + 1000) {
+                        queue.push(i).unwrap();
+                    }
+                });
+            }
+            let consumers = (0..4)
+                .map(|_| {
+                    scope.spawn(|| (0..1000).map(|_| queue.pop().unwrap()).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+            consumers
+                .into_iter()
+                .flat_map(|consumer| consumer.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        received.sort_unstable();
+        assert_eq!(received, (0..4000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,188 @@
+//! A queue which makes room for new values by discarding the oldest ones.
+
+use crate::{Closed, Iter, PopError, PopTimeoutError, RingQueue, TryIter};
+
+use std::{
+    iter::FromIterator,
+    time::{Duration, Instant},
+};
+
+/// A concurrent fixed-size queue whose producers never block. Pushing into a full queue evicts the
+/// oldest value, so consumers always see the most recent `LEN` values.
+#[derive(Debug)]
+pub struct OverwritingRingQueue<T, const LEN: usize> {
+    queue: RingQueue<T, LEN>,
+}
+
+impl<T, const LEN: usize> OverwritingRingQueue<T, LEN> {
+    /// Create a new `OverwritingRingQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and every value in it has been popped.
+    pub fn pop(&self) -> Result<T, Closed> {
+        self.queue.pop()
+    }
+
+    /// Gets the first value out of the queue if there is one. Returns `PopError::Empty`
+    /// immediately if the queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.queue.try_pop()
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but for no
+    /// longer than `timeout`. Returns `PopTimeoutError::Timeout` if the queue was still empty when
+    /// the timeout elapsed, or `PopTimeoutError::Closed` if the queue was closed and empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.queue.pop_timeout(timeout)
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open, but not
+    /// past `deadline`. Fails in the same ways as `pop_timeout`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.queue.pop_until(deadline)
+    }
+
+    /// Removes every value that is currently in the queue, in order, without blocking.
+    pub fn drain(&self) -> Vec<T> {
+        self.queue.drain()
+    }
+
+    /// Adds a new value to the end of the queue, evicting the first value if the queue is full.
+    /// Returns the value which did not make it into the queue: the evicted value if there was one,
+    /// or `value` itself if the queue is closed.
+    pub fn push(&self, value: T) -> Option<T> {
+        self.queue.force_push(value)
+    }
+
+    /// Adds every value from `values` to the end of the queue as one contiguous batch, evicting as
+    /// many of the oldest values as necessary. If there are more than `LEN` values, only the last
+    /// `LEN` of them are kept. Returns every value which did not make it into the queue, oldest
+    /// first, which is all of `values` if the queue is closed.
+    pub fn push_all<I>(&self, values: I) -> Vec<T>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        span!("push_all");
+        let mut inner = self.queue.inner.lock();
+        if inner.closed || LEN == 0 {
+            return values.into_iter().collect();
+        }
+        let mut evicted = vec![];
+        for value in values {
+            if inner.len() == LEN {
+                evicted.push(inner.pop());
+            }
+            inner.push(value);
+        }
+        trace!("Force-pushed batch into queue, {} queued", inner.len());
+        self.queue.notify_pushed(&mut inner, LEN);
+        evicted
+    }
+
+    /// Returns an iterator which pops values out of the queue, blocking while it is empty. The
+    /// iterator ends once the queue has been closed and every value in it has been popped.
+    pub fn iter(&self) -> Iter<'_, T, LEN> {
+        self.queue.iter()
+    }
+
+    /// Returns an iterator which pops the values that are currently in the queue. The iterator
+    /// ends as soon as the queue is empty instead of blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T, LEN> {
+        self.queue.try_iter()
+    }
+
+    /// Gets a copy of the first value in the queue without removing it. Returns `None` if the
+    /// queue is empty.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.queue.peek()
+    }
+
+    /// Closes the queue. Values that are already in the queue can still be popped, but every
+    /// attempt to push a new value will fail and every thread blocked in `pop` is woken.
+    pub fn close(&self) {
+        self.queue.close()
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+impl<T, const LEN: usize> Clone for OverwritingRingQueue<T, LEN>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T, const LEN: usize> defmt::Format for OverwritingRingQueue<T, LEN> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "OverwritingRingQueue {{ queue: {} }}", self.queue);
+    }
+}
+
+impl<T, const LEN: usize> Default for OverwritingRingQueue<T, LEN> {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+        }
+    }
+}
+
+/// Creates a queue containing the last `LEN` values from the iterator in order.
+impl<T, const LEN: usize> FromIterator<T> for OverwritingRingQueue<T, LEN> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let ret = Self::new();
+        ret.push_all(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_keeps_most_recent_values() {
+        let queue = OverwritingRingQueue::<u32, 3>::new();
+        for i in 0..5 {
+            queue.push(i);
+        }
+        let clone = queue.clone();
+        assert_eq!(queue.drain(), vec![2, 3, 4]);
+        assert_eq!(clone.push_all(5..7), vec![2, 3]);
+        assert_eq!(clone.drain(), vec![4, 5, 6]);
+        assert_eq!(clone.push_all(0..5), vec![0, 1]);
+        assert_eq!(clone.drain(), vec![2, 3, 4]);
+    }
+}
@@ -0,0 +1,37 @@
+//! Keeps values which different threads write to in separate cache lines.
+
+use core::ops::Deref;
+
+// A value which starts its own cache line and fills it, so that writes to it never invalidate the
+// cache line of a neighboring field. Without the `cache-padding` feature it is just the value.
+#[cfg_attr(feature = "cache-padding", repr(align(64)))]
+#[derive(Debug, Default)]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::mem;
+
+    #[cfg(feature = "cache-padding")]
+    #[test]
+    fn padded_values_fill_a_cache_line() {
+        assert_eq!(mem::align_of::<CachePadded<u8>>(), 64);
+        assert_eq!(mem::size_of::<CachePadded<u8>>(), 64);
+    }
+
+    #[cfg(not(feature = "cache-padding"))]
+    #[test]
+    fn unpadded_values_keep_their_layout() {
+        assert_eq!(mem::size_of::<CachePadded<u8>>(), 1);
+    }
+}
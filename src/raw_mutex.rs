@@ -0,0 +1,116 @@
+//! A queue protected by a mutex that the user supplies, without `std`.
+//!
+//! `RingQueue` can be locked by any `lock_api::RawMutex` as well, but it puts threads that wait for
+//! it to change to sleep with `std`, because `lock_api` doesn't provide a condition variable.
+//! `RawMutexRingQueue` is the non-blocking subset of the queue for code that has to use a mutex of
+//! its own where `std` isn't available.
+
+use crate::RingBuffer;
+
+use lock_api::{Mutex, RawMutex};
+
+/// A concurrent fixed-size queue which protects its values with any mutex that implements
+/// `lock_api::RawMutex`, such as an RTOS mutex or a priority-inheritance mutex. `lock_api` has no
+/// condition variable to wait on, so every operation returns immediately.
+#[derive(Debug)]
+pub struct RawMutexRingQueue<R, T, const LEN: usize>
+where
+    R: RawMutex,
+{
+    ring: Mutex<R, RingBuffer<T, LEN>>,
+}
+
+impl<R, T, const LEN: usize> RawMutexRingQueue<R, T, LEN>
+where
+    R: RawMutex,
+{
+    /// Create a new `RawMutexRingQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.ring.lock().len()
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue currently contains `LEN` values.
+    pub fn is_full(&self) -> bool {
+        self.len() == LEN
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value if the
+    /// queue is full. Only waits for as long as `R` takes to lock.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        self.ring.lock().push(value)
+    }
+
+    /// Gets the first value out of the queue if there is one. Only waits for as long as `R` takes
+    /// to lock.
+    pub fn try_pop(&self) -> Option<T> {
+        self.ring.lock().pop()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<R, T, const LEN: usize> defmt::Format for RawMutexRingQueue<R, T, LEN>
+where
+    R: RawMutex,
+{
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "RawMutexRingQueue {{ ring: {} }}", *self.ring.lock());
+    }
+}
+
+impl<R, T, const LEN: usize> Default for RawMutexRingQueue<R, T, LEN>
+where
+    R: RawMutex,
+{
+    fn default() -> Self {
+        Self {
+            ring: Mutex::new(RingBuffer::new()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn user_supplied_mutex_protects_values() {
+        let queue = Arc::new(RawMutexRingQueue::<parking_lot::RawMutex, u32, 4>::new());
+        let producer = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                for i in 0..100 {
+                    while queue.try_push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+        let mut received = vec![];
+        while received.len() < 100 {
+            match queue.try_pop() {
+                Some(value) => received.push(value),
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+}
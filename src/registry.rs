@@ -0,0 +1,155 @@
+//! A process-wide registry of shared `RingQueue`s, for code which needs to find a queue by name
+//! instead of being handed it. A queue stays registered until it is unregistered, so it lives at
+//! least as long as its registration.
+
+use crate::{sync::Mutex, RingQueue};
+
+use std::{any::Any, collections::BTreeMap, sync::Arc};
+
+// Every registered queue, keyed by name. The queues are type-erased so that queues of different
+// types can share the map; `get` recovers the type.
+static QUEUES: Mutex<BTreeMap<String, Arc<dyn Any + Send + Sync>>> = Mutex::new(BTreeMap::new());
+
+/// Registers `queue` under `name`. Returns `queue` if there is already a queue registered under
+/// `name`, whatever its type.
+pub fn register<T, const LEN: usize>(
+    name: &str,
+    queue: Arc<RingQueue<T, LEN>>,
+) -> Result<(), Arc<RingQueue<T, LEN>>>
+where
+    T: Send + 'static,
+{
+    let mut queues = QUEUES.lock();
+    if queues.contains_key(name) {
+        return Err(queue);
+    }
+    queues.insert(name.to_owned(), queue);
+    Ok(())
+}
+
+/// Gets the queue registered under `name`. Returns `None` if there is no such queue or if it is not
+/// a `RingQueue<T, LEN>`.
+pub fn get<T, const LEN: usize>(name: &str) -> Option<Arc<RingQueue<T, LEN>>>
+where
+    T: Send + 'static,
+{
+    let queue = Arc::clone(QUEUES.lock().get(name)?);
+    queue.downcast().ok()
+}
+
+/// Gets the queue registered under `name`, first registering a new queue created by `f` if there
+/// is no queue registered under `name`. Returns `None` if the registered queue is not a
+/// `RingQueue<T, LEN>`.
+///
+/// `f` is called without the registry locked, so it may use the registry itself. If another thread
+/// registers a queue under `name` while `f` runs, that queue is returned and `f`'s is dropped, also
+/// without the registry locked.
+pub fn get_or_register_with<T, F, const LEN: usize>(
+    name: &str,
+    f: F,
+) -> Option<Arc<RingQueue<T, LEN>>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> RingQueue<T, LEN>,
+{
+    if let Some(queue) = QUEUES.lock().get(name) {
+        return Arc::clone(queue).downcast().ok();
+    }
+    let new: Arc<dyn Any + Send + Sync> = Arc::new(f());
+    let mut queues = QUEUES.lock();
+    let queue = Arc::clone(queues.entry(name.to_owned()).or_insert_with(|| Arc::clone(&new)));
+    drop(queues);
+    // If another queue was registered first, this drops `new` only now that the registry is
+    // unlocked, in case its values use the registry when they are dropped.
+    drop(new);
+    queue.downcast().ok()
+}
+
+/// Removes the queue registered under `name` from the registry. Handles to the queue which have
+/// already been given out remain usable. Returns whether there was a queue registered under
+/// `name`.
+pub fn unregister(name: &str) -> bool {
+    // The queue is dropped after the registry is unlocked if this was its last handle.
+    let queue = QUEUES.lock().remove(name);
+    queue.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The registry is shared by every test in the process, so each test uses its own names.
+
+    #[test]
+    fn registered_queues_can_be_found_by_name() {
+        let audio = Arc::new(RingQueue::<u32, 4>::new());
+        assert!(register("registry-test-audio", Arc::clone(&audio)).is_ok());
+        assert!(register("registry-test-audio", Arc::new(RingQueue::<u32, 4>::new())).is_err());
+        get::<u32, 4>("registry-test-audio").unwrap().push(3).unwrap();
+        assert_eq!(audio.try_pop(), Ok(3));
+        assert!(get::<u32, 8>("registry-test-audio").is_none());
+        assert!(get::<u64, 4>("registry-test-audio").is_none());
+        assert!(unregister("registry-test-audio"));
+        assert!(!unregister("registry-test-audio"));
+        assert!(get::<u32, 4>("registry-test-audio").is_none());
+    }
+
+    #[test]
+    fn get_or_register_with_only_creates_one_queue() {
+        let first = get_or_register_with::<u32, _, 2>("registry-test-shared", RingQueue::new);
+        let second = get_or_register_with::<u32, _, 2>("registry-test-shared", || unreachable!());
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+        assert!(get_or_register_with::<u8, _, 2>("registry-test-shared", RingQueue::new).is_none());
+        assert!(unregister("registry-test-shared"));
+    }
+
+    #[test]
+    fn get_or_register_with_can_use_the_registry_while_creating() {
+        let queue = get_or_register_with::<u32, _, 2>("registry-test-outer", || {
+            assert!(get::<u32, 2>("registry-test-outer").is_none());
+            let inner = get_or_register_with::<u32, _, 2>("registry-test-inner", RingQueue::new);
+            inner.unwrap().push(1).unwrap();
+            RingQueue::new()
+        });
+        assert!(queue.is_some());
+        assert_eq!(get::<u32, 2>("registry-test-inner").unwrap().try_pop(), Ok(1));
+        assert!(unregister("registry-test-outer"));
+        assert!(unregister("registry-test-inner"));
+    }
+
+    #[test]
+    fn get_or_register_with_keeps_a_queue_registered_while_creating() {
+        let existing = Arc::new(RingQueue::<u32, 2>::new());
+        let queue = get_or_register_with::<u32, _, 2>("registry-test-raced", || {
+            register("registry-test-raced", Arc::clone(&existing)).unwrap();
+            RingQueue::new()
+        });
+        assert!(Arc::ptr_eq(&queue.unwrap(), &existing));
+        assert!(unregister("registry-test-raced"));
+    }
+
+    #[test]
+    fn queues_are_dropped_without_the_registry_locked() {
+        // Uses the registry when it is dropped.
+        #[derive(Debug)]
+        struct LooksUp;
+
+        impl Drop for LooksUp {
+            fn drop(&mut self) {
+                assert!(get::<LooksUp, 1>("registry-test-unrelated").is_none());
+            }
+        }
+
+        let existing = Arc::new(RingQueue::<LooksUp, 1>::new());
+        existing.push(LooksUp).unwrap();
+        let queue = get_or_register_with::<LooksUp, _, 1>("registry-test-dropped", || {
+            register("registry-test-dropped", Arc::clone(&existing)).unwrap();
+            let losing = RingQueue::new();
+            losing.push(LooksUp).unwrap();
+            losing
+        });
+        assert!(Arc::ptr_eq(&queue.unwrap(), &existing));
+        drop(existing);
+        assert!(unregister("registry-test-dropped"));
+    }
+}
@@ -0,0 +1,751 @@
+//! The ring arithmetic shared by every queue, usable without `std` or an allocator.
+
+use core::{
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+};
+
+// Reduces `index` modulo `len`. Uses a bitmask instead of a division when `len` is a power of two.
+// Every caller either passes a constant `len` or one that is read from storage whose length is
+// known once the call is inlined, so the choice between the two is made at compile time.
+#[cfg(feature = "std")]
+#[inline(always)]
+pub(crate) const fn wrap(index: usize, len: usize) -> usize {
+    if len.is_power_of_two() {
+        index & (len - 1)
+    } else {
+        index % len
+    }
+}
+
+/// Memory which a `StorageRingBuffer` can keep its values in.
+///
+/// # Safety
+///
+/// `as_slice` and `as_mut_slice` must always return slices of the same length which refer to the
+/// same memory, and nothing other than the `StorageRingBuffer` may write to that memory.
+pub unsafe trait Storage<T> {
+    /// The memory to keep values in.
+    fn as_slice(&self) -> &[MaybeUninit<T>];
+
+    /// The memory to keep values in.
+    fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>];
+}
+
+// SAFETY: An array always has the same length and is only accessible through its owner.
+unsafe impl<T, const LEN: usize> Storage<T> for [MaybeUninit<T>; LEN] {
+    fn as_slice(&self) -> &[MaybeUninit<T>] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        self
+    }
+}
+
+// SAFETY: A mutable reference always refers to the same slice and excludes every other access to
+//         it for as long as it lives.
+unsafe impl<T> Storage<T> for &mut [MaybeUninit<T>] {
+    fn as_slice(&self) -> &[MaybeUninit<T>] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        self
+    }
+}
+
+// SAFETY: A boxed slice never changes its length and is only accessible through its owner.
+#[cfg(feature = "std")]
+unsafe impl<T> Storage<T> for Box<[MaybeUninit<T>]> {
+    fn as_slice(&self) -> &[MaybeUninit<T>] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        self
+    }
+}
+
+// SAFETY: A boxed array always has the same length and is only accessible through its owner.
+#[cfg(feature = "std")]
+unsafe impl<T, const LEN: usize> Storage<T> for Box<[MaybeUninit<T>; LEN]> {
+    fn as_slice(&self) -> &[MaybeUninit<T>] {
+        &**self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        &mut **self
+    }
+}
+
+/// A fixed-size queue which is not synchronized. It needs neither `std` nor an allocator, so it
+/// can be used on its own on targets which have no `Mutex`.
+pub type RingBuffer<T, const LEN: usize> = StorageRingBuffer<T, [MaybeUninit<T>; LEN]>;
+
+/// A `StorageRingBuffer` which keeps its values in a slice provided by the caller instead of
+/// inside itself. This lets the values live in a specific linker section or region of memory,
+/// such as a `static` placed in DMA-capable RAM. Its capacity is the length of the slice.
+pub type SliceRingBuffer<'a, T> = StorageRingBuffer<T, &'a mut [MaybeUninit<T>]>;
+
+/// A `StorageRingBuffer` which keeps its values on the heap. Its capacity is chosen at runtime.
+#[cfg(feature = "std")]
+pub type BoxedRingBuffer<T> = StorageRingBuffer<T, Box<[MaybeUninit<T>]>>;
+
+/// A fixed-size queue which is not synchronized and keeps its values in `S`. The capacity of the
+/// queue is the length of the storage.
+#[derive(Debug)]
+pub struct StorageRingBuffer<T, S>
+where
+    S: Storage<T>,
+{
+    // INVARIANT: The elements of `self.values` whose counters are in `self.head..self.tail` are
+    //            always initialized. No guarantee is made about whether any other elements are
+    //            initialized.
+    values: S,
+    // The number of values which have ever been popped. Its element is `self.index(self.head)`.
+    head: u64,
+    // The number of values which have ever been pushed. Both counters wrap around at `u64::MAX`,
+    // which can't be reached by any real program, and the buffer holds `tail - head` values.
+    tail: u64,
+    _values: PhantomData<T>,
+}
+
+impl<T, const LEN: usize> StorageRingBuffer<T, [MaybeUninit<T>; LEN]> {
+    /// Create a new, empty `RingBuffer`.
+    pub const fn new() -> Self {
+        Self::with_storage([const { MaybeUninit::uninit() }; LEN])
+    }
+
+    /// Creates a copy of the buffer by copying its storage in one go instead of one value at a
+    /// time as `clone` does.
+    pub fn copied(&self) -> Self
+    where
+        T: Copy,
+    {
+        // This method upholds the invariant on `self.values` because the copy has every element of
+        // the original at the same index, including the initialized ones.
+        Self {
+            values: self.values,
+            head: self.head,
+            tail: self.tail,
+            _values: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> StorageRingBuffer<T, &'a mut [MaybeUninit<T>]> {
+    /// Create a new, empty `SliceRingBuffer` which stores its values in `storage`. Anything
+    /// already in `storage` is treated as uninitialized and will never be dropped.
+    pub fn new(storage: &'a mut [MaybeUninit<T>]) -> Self {
+        Self::with_storage(storage)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> StorageRingBuffer<T, Box<[MaybeUninit<T>]>> {
+    /// Create a new, empty `BoxedRingBuffer` which can hold `capacity` values.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_storage((0..capacity).map(|_| MaybeUninit::uninit()).collect())
+    }
+}
+
+impl<T, S> StorageRingBuffer<T, S>
+where
+    S: Storage<T>,
+{
+    /// Create a new, empty `StorageRingBuffer` which stores its values in `storage`. Anything
+    /// already in `storage` is treated as uninitialized and will never be dropped.
+    pub const fn with_storage(storage: S) -> Self {
+        Self {
+            values: storage,
+            head: 0,
+            tail: 0,
+            _values: PhantomData,
+        }
+    }
+
+    /// The number of values currently in the buffer.
+    pub fn len(&self) -> usize {
+        // The difference never exceeds the capacity, so it always fits in a `usize`.
+        self.tail.wrapping_sub(self.head) as usize
+    }
+
+    /// Whether the buffer currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// Whether the buffer currently contains as many values as its storage can hold.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// The maximum number of values that the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.values.as_slice().len()
+    }
+
+    /// Removes the first value from the buffer. Returns `None` if the buffer is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        // This method upholds the invariant on `self.values` because it moves the head past the
+        // element that it returns, which removes only that element from the window covered by the
+        // invariant.
+        let index = self.index(self.head);
+        self.head = self.head.wrapping_add(1);
+        let ret = mem::replace(&mut self.values.as_mut_slice()[index], MaybeUninit::uninit());
+        // SAFETY: This use of `assume_init` is safe because it is an invariant that the element at
+        //         the head is initialized when the buffer is not empty.
+        Some(unsafe { ret.assume_init() })
+    }
+
+    /// Adds a value to the end of the buffer. Returns the value if the buffer is full. Never
+    /// allocates and runs in constant time.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        // This method upholds the invariant on `self.values` because it inserts the new value as
+        // the first element after the end of the window covered by the invariant before moving the
+        // tail past it.
+        let end = self.index(self.tail);
+        self.values.as_mut_slice()[end] = MaybeUninit::new(value);
+        self.tail = self.tail.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Copies as many values from the front of `values` to the end of the buffer as there is room
+    /// for. Returns the number of values copied. Copies at most two runs of memory.
+    pub fn push_slice(&mut self, values: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let capacity = self.capacity();
+        let len = values.len().min(capacity - self.len());
+        if len == 0 {
+            return 0;
+        }
+        // SAFETY: This cast is safe because `MaybeUninit<T>` has the same layout as `T` and every
+        //         `T` is a valid `MaybeUninit<T>`.
+        let values = unsafe { &*(&values[..len] as *const [T] as *const [MaybeUninit<T>]) };
+        let start = self.index(self.tail);
+        let first_len = len.min(capacity - start);
+        let storage = self.values.as_mut_slice();
+        storage[start..start + first_len].copy_from_slice(&values[..first_len]);
+        storage[..len - first_len].copy_from_slice(&values[first_len..]);
+        // This method upholds the invariant on `self.values` because it initialized the `len`
+        // elements after the end of the window covered by the invariant before moving the tail
+        // past them.
+        self.tail = self.tail.wrapping_add(len as u64);
+        len
+    }
+
+    /// Moves as many values from the front of the buffer into the front of `out` as fit. Returns
+    /// the number of values moved. Copies at most two runs of memory.
+    pub fn pop_slice(&mut self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let len = self.copy_to_slice(out);
+        // This method upholds the invariant on `self.values` because it moves the head past only
+        // the elements that were copied out, which `T: Copy` means need not be dropped.
+        self.head = self.head.wrapping_add(len as u64);
+        len
+    }
+
+    /// Copies as many values from the front of the buffer into the front of `out` as fit, without
+    /// removing them. Returns the number of values copied. Copies at most two runs of memory.
+    pub fn copy_to_slice(&self, out: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let (first, second) = self.as_slices();
+        let first_len = first.len().min(out.len());
+        let second_len = second.len().min(out.len() - first_len);
+        out[..first_len].copy_from_slice(&first[..first_len]);
+        out[first_len..first_len + second_len].copy_from_slice(&second[..second_len]);
+        first_len + second_len
+    }
+
+    /// Gets a reference to the first value in the buffer. Returns `None` if the buffer is empty.
+    pub fn front(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        // SAFETY: This use of `assume_init_ref` is safe because it is an invariant that the element
+        //         at the head is initialized when the buffer is not empty.
+        Some(unsafe { self.values.as_slice()[self.index(self.head)].assume_init_ref() })
+    }
+
+    /// Gets a reference to the value `index` values from the front of the buffer. Returns `None`
+    /// if there are not that many values in the buffer.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let index = self.index(self.head.wrapping_add(index as u64));
+        // SAFETY: This use of `assume_init_ref` is safe because it is an invariant that the
+        //         elements whose counters are in `self.head..self.tail` are initialized.
+        Some(unsafe { self.values.as_slice()[index].assume_init_ref() })
+    }
+
+    /// Gets a mutable reference to the first value in the buffer. Returns `None` if the buffer is
+    /// empty.
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = self.index(self.head);
+        // SAFETY: This use of `assume_init_mut` is safe because it is an invariant that the element
+        //         at the head is initialized when the buffer is not empty.
+        Some(unsafe { self.values.as_mut_slice()[index].assume_init_mut() })
+    }
+
+    /// Gets the values in the buffer, in order, as two slices. The second slice is only non-empty
+    /// when the values wrap around the end of the buffer's storage.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let capacity = self.capacity();
+        let len = self.len();
+        let start = if capacity == 0 { 0 } else { self.index(self.head) };
+        let first_len = len.min(capacity - start);
+        let values = self.values.as_slice();
+        let (first, second) = (&values[start..start + first_len], &values[..len - first_len]);
+        // SAFETY: These casts are safe because `MaybeUninit<T>` has the same layout as `T` and it
+        //         is an invariant that the elements whose counters are in `self.head..self.tail`,
+        //         which are exactly the elements in the two slices, are initialized.
+        unsafe {
+            (
+                &*(first as *const [MaybeUninit<T>] as *const [T]),
+                &*(second as *const [MaybeUninit<T>] as *const [T]),
+            )
+        }
+    }
+
+    /// Moves the values in the buffer within its storage so that they form one slice, which is
+    /// returned. The values keep their order. Runs in time linear in the capacity of the buffer.
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        let len = self.len();
+        if len > 0 {
+            let start = self.index(self.head);
+            self.values.as_mut_slice().rotate_left(start);
+        }
+        // This method upholds the invariant on `self.values` because the rotation moved the
+        // element with counter `self.head` to index 0 and every later element along with it, which
+        // is exactly where renumbering the counters from 0 expects them to be.
+        self.head = 0;
+        self.tail = len as u64;
+        let values = &mut self.values.as_mut_slice()[..len];
+        // SAFETY: This cast is safe because `MaybeUninit<T>` has the same layout as `T` and it is
+        //         an invariant that the elements whose counters are in `self.head..self.tail`,
+        //         which are exactly the elements in the slice, are initialized.
+        unsafe { &mut *(values as *mut [MaybeUninit<T>] as *mut [T]) }
+    }
+
+    /// Removes and drops every value in the buffer.
+    pub fn clear(&mut self) {
+        // Popping every remaining value drops exactly the elements which the invariant on
+        // `self.values` guarantees to be initialized.
+        while self.pop().is_some() {}
+    }
+
+    /// Removes every value for which `f` returns `false`, keeping the rest in order. Calls `f` on
+    /// each value once, in order.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut f = f;
+        self.retain_mut(|value| f(value));
+    }
+
+    /// Removes every value for which `f` returns `false`, keeping the rest in order. Calls `f` on
+    /// each value once, in order, so it can also change the values that it keeps.
+    pub fn retain_mut<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut f = f;
+        let values = self.make_contiguous();
+        let mut kept = 0;
+        for i in 0..values.len() {
+            if f(&mut values[i]) {
+                values.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+
+    /// Drops the values at the end of the buffer so that only the first `len` are left. Does
+    /// nothing if there are already no more than `len` values in the buffer.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len() > len {
+            // This method upholds the invariant on `self.values` because the element that the tail
+            // is moved back past is dropped and will never be read again.
+            self.tail = self.tail.wrapping_sub(1);
+            let index = self.index(self.tail);
+            // SAFETY: This use of `assume_init_drop` is safe because it is an invariant that the
+            //         element at the end of the buffer was initialized.
+            unsafe { self.values.as_mut_slice()[index].assume_init_drop() };
+        }
+    }
+
+    /// Drops the values at the front of the buffer so that only the last `len` are left. Does
+    /// nothing if there are already no more than `len` values in the buffer.
+    pub fn truncate_front(&mut self, len: usize) {
+        while self.len() > len {
+            self.pop();
+        }
+    }
+
+    // The uninitialized element just past the end of the buffer, which the next value pushed into
+    // the buffer will occupy. Returns `None` if the buffer is full.
+    #[cfg(feature = "std")]
+    pub(crate) fn vacant_mut(&mut self) -> Option<&mut MaybeUninit<T>> {
+        if self.is_full() {
+            return None;
+        }
+        let end = self.index(self.tail);
+        Some(&mut self.values.as_mut_slice()[end])
+    }
+
+    // Adds the element returned by `vacant_mut` to the end of the buffer.
+    //
+    // SAFETY: Calling this function is only safe when the buffer is not full and the element just
+    //         past its end has been initialized.
+    #[cfg(feature = "std")]
+    pub(crate) unsafe fn push_vacant(&mut self) {
+        // This method upholds the invariant on `self.values` because the caller guarantees that
+        // the first element after the end of the window covered by the invariant is initialized.
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    // Moves the first value in the buffer into `out`. Returns whether there was a value to move.
+    #[cfg(feature = "std")]
+    pub(crate) fn pop_into(&mut self, out: &mut MaybeUninit<T>) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        // This method upholds the invariant on `self.values` in the same way as `pop`.
+        let index = self.index(self.head);
+        self.head = self.head.wrapping_add(1);
+        let src = self.values.as_slice()[index].as_ptr();
+        // SAFETY: This copy is safe because it is an invariant that the element at the head was
+        //         initialized, and moving the head past it means that it will never be read again.
+        unsafe { core::ptr::copy_nonoverlapping(src, out.as_mut_ptr(), 1) };
+        true
+    }
+
+    // The index in `self.values` of the element with the given counter.
+    fn index(&self, counter: u64) -> usize {
+        let len = self.capacity();
+        if len.is_power_of_two() {
+            // Masking off the high bits of a power-of-two capacity gives the right answer even
+            // after the counter wraps around and on targets with 32-bit `usize`.
+            counter as usize & (len - 1)
+        } else {
+            // The remainder is less than `len`, so it always fits in a `usize`.
+            (counter % len as u64) as usize
+        }
+    }
+}
+
+impl<T, const LEN: usize> Clone for StorageRingBuffer<T, [MaybeUninit<T>; LEN]>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        // The clone only covers the values which have been cloned so far, so if cloning a value
+        // panics, dropping the clone drops exactly those values instead of leaking them.
+        let mut ret = Self::new();
+        ret.head = self.head;
+        ret.tail = self.head;
+        for offset in 0..self.len() {
+            let index = self.index(self.head.wrapping_add(offset as u64));
+            // SAFETY: This use of `assume_init_ref` is safe because it is an invariant that the
+            //         elements whose counters are in `self.head..self.tail` are initialized.
+            let value = unsafe { self.values[index].assume_init_ref() }.clone();
+            // This upholds the invariant on `ret.values` because the clone has the same counters
+            // and capacity as `self`, so `index` is also the element just past its tail.
+            ret.values[index] = MaybeUninit::new(value);
+            ret.tail = ret.tail.wrapping_add(1);
+        }
+        ret
+    }
+}
+
+impl<T, S> Drop for StorageRingBuffer<T, S>
+where
+    S: Storage<T>,
+{
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T, S> defmt::Format for StorageRingBuffer<T, S>
+where
+    S: Storage<T>,
+{
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "RingBuffer {{ len: {=usize}, capacity: {=usize} }}",
+            self.len(),
+            self.capacity()
+        );
+    }
+}
+
+impl<T, const LEN: usize> Default for StorageRingBuffer<T, [MaybeUninit<T>; LEN]> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn as_slices_splits_where_values_wrap() {
+        let mut ring = RingBuffer::<u32, 4>::new();
+        assert_eq!(ring.as_slices(), (&[][..], &[][..]));
+        for i in 0..3 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.as_slices(), (&[0, 1, 2][..], &[][..]));
+        ring.pop();
+        ring.pop();
+        for i in 3..6 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.as_slices(), (&[2, 3][..], &[4, 5][..]));
+        assert_eq!(RingBuffer::<u32, 0>::new().as_slices(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn make_contiguous_keeps_values_in_order() {
+        let mut ring = RingBuffer::<&str, 3>::new();
+        for value in ["0", "1", "2"].iter() {
+            ring.push(*value).unwrap();
+        }
+        ring.pop();
+        ring.push("3").unwrap();
+        assert_eq!(ring.make_contiguous(), ["1", "2", "3"]);
+        assert_eq!(ring.as_slices().1, &[] as &[&str]);
+        assert_eq!(ring.pop(), Some("1"));
+        ring.push("4").unwrap();
+        assert!(ring.is_full());
+        assert_eq!(ring.make_contiguous(), ["2", "3", "4"]);
+    }
+
+    #[test]
+    fn retain_keeps_values_in_order() {
+        let mut ring = StorageRingBuffer::<u32, [MaybeUninit<u32>; 5]>::new();
+        for i in 0..3 {
+            ring.push(i).unwrap();
+        }
+        ring.pop().unwrap();
+        ring.pop().unwrap();
+        for i in 3..7 {
+            ring.push(i).unwrap();
+        }
+        ring.retain(|&value| value % 2 == 0);
+        assert_eq!(ring.as_slices(), (&[2, 4, 6][..], &[][..]));
+        ring.push(7).unwrap();
+        ring.push(8).unwrap();
+        assert_eq!(ring.len(), 5);
+        assert_eq!((ring.get(0), ring.get(4), ring.get(5)), (Some(&2), Some(&8), None));
+        ring.retain_mut(|value| {
+            *value += 1;
+            *value < 8
+        });
+        assert_eq!(ring.as_slices(), (&[3, 5, 7][..], &[][..]));
+        ring.truncate(2);
+        ring.truncate_front(1);
+        assert_eq!(ring.as_slices(), (&[5][..], &[][..]));
+    }
+
+    #[test]
+    fn slices_wrap_around() {
+        let mut ring = StorageRingBuffer::<u8, [MaybeUninit<u8>; 5]>::new();
+        assert_eq!(ring.push_slice(&[0, 1, 2]), 3);
+        let mut out = [0; 2];
+        assert_eq!(ring.pop_slice(&mut out), 2);
+        assert_eq!(out, [0, 1]);
+        assert_eq!(ring.push_slice(&[3, 4, 5, 6, 7]), 4);
+        assert_eq!(ring.as_slices(), (&[2, 3, 4][..], &[5, 6][..]));
+        let mut out = [0; 6];
+        assert_eq!(ring.pop_slice(&mut out), 5);
+        assert_eq!(out, [2, 3, 4, 5, 6, 0]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn copied_matches_clone() {
+        let mut ring = RingBuffer::<u8, 3>::new();
+        for i in 0..3 {
+            ring.push(i).unwrap();
+        }
+        ring.pop().unwrap();
+        ring.push(3).unwrap();
+        let mut copy = ring.copied();
+        assert_eq!(copy.as_slices(), ring.clone().as_slices());
+        assert_eq!(copy.pop(), Some(1));
+        assert_eq!(ring.len(), 3);
+    }
+
+    #[test]
+    fn push_and_pop_wrap_around() {
+        let mut ring = RingBuffer::<u32, 3>::new();
+        assert_eq!(ring.pop(), None);
+        for i in 0..3 {
+            ring.push(i).unwrap();
+        }
+        assert!(ring.is_full());
+        assert_eq!(ring.push(3), Err(3));
+        assert_eq!(ring.pop(), Some(0));
+        ring.push(3).unwrap();
+        let clone = ring.clone();
+        for i in 1..4 {
+            assert_eq!(ring.front(), Some(&i));
+            assert_eq!(ring.pop(), Some(i));
+        }
+        assert!(ring.is_empty());
+        assert_eq!(clone.len(), 3);
+    }
+
+    #[test]
+    fn values_live_in_provided_storage() {
+        static mut STORAGE: [MaybeUninit<u32>; 3] = [MaybeUninit::uninit(); 3];
+        // SAFETY: This is the only reference to `STORAGE` that is ever created.
+        let mut ring = SliceRingBuffer::new(unsafe { &mut *core::ptr::addr_of_mut!(STORAGE) });
+        assert_eq!(ring.capacity(), 3);
+        for i in 0..3 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.push(3), Err(3));
+        assert_eq!(ring.pop(), Some(0));
+        ring.push(3).unwrap();
+        for i in 1..4 {
+            assert_eq!(ring.front(), Some(&i));
+            assert_eq!(ring.pop(), Some(i));
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn boxed_storage_has_runtime_capacity() {
+        let mut ring = BoxedRingBuffer::with_capacity(2);
+        assert_eq!(ring.capacity(), 2);
+        ring.push(1).unwrap();
+        ring.push(2).unwrap();
+        assert_eq!(ring.push(3), Err(3));
+        assert_eq!(ring.pop(), Some(1));
+    }
+
+    #[test]
+    fn end_index_wraps_around_storage() {
+        let mut ring = RingBuffer::<u32, 3>::new();
+        for round in 0..4 {
+            ring.push(round).unwrap();
+            ring.push(round + 10).unwrap();
+            assert_eq!(ring.index(ring.tail), ((round as usize + 1) * 2) % 3);
+            assert_eq!(ring.pop(), Some(round));
+            assert_eq!(ring.pop(), Some(round + 10));
+        }
+    }
+
+    #[test]
+    fn counters_wrap_around_u64() {
+        let mut ring = RingBuffer::<u32, 4>::new();
+        ring.head = u64::MAX - 1;
+        ring.tail = u64::MAX - 1;
+        for i in 0..4 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.tail, 2);
+        assert_eq!(ring.len(), 4);
+        assert!(ring.is_full());
+        assert_eq!(ring.push(4), Err(4));
+        let clone = ring.clone();
+        for i in 0..4 {
+            assert_eq!(ring.pop(), Some(i));
+        }
+        assert!(ring.is_empty());
+        assert_eq!(clone.len(), 4);
+        assert_eq!(clone.front(), Some(&0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn clone_drops_cloned_values_if_clone_panics() {
+        use std::{
+            cell::Cell,
+            panic::{self, AssertUnwindSafe},
+            rc::Rc,
+        };
+
+        // Counts how many times it has been dropped, and panics on its `limit`th clone.
+        #[derive(Debug)]
+        struct Fragile {
+            drops: Rc<Cell<usize>>,
+            clones: Rc<Cell<usize>>,
+            limit: usize,
+        }
+
+        impl Clone for Fragile {
+            fn clone(&self) -> Self {
+                self.clones.set(self.clones.get() + 1);
+                assert!(self.clones.get() < self.limit, "clone limit reached");
+                Self {
+                    drops: Rc::clone(&self.drops),
+                    clones: Rc::clone(&self.clones),
+                    limit: self.limit,
+                }
+            }
+        }
+
+        impl Drop for Fragile {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let clones = Rc::new(Cell::new(0));
+        let mut ring = RingBuffer::<Fragile, 4>::new();
+        // Start part of the way through the storage so that the clone has to wrap around.
+        ring.head = 3;
+        ring.tail = 3;
+        for _ in 0..3 {
+            let value = Fragile {
+                drops: Rc::clone(&drops),
+                clones: Rc::clone(&clones),
+                limit: 3,
+            };
+            ring.push(value).unwrap_or_else(|_| unreachable!());
+        }
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| ring.clone())).is_err());
+        // Both values which were cloned before the panic have been dropped.
+        assert_eq!(drops.get(), 2);
+        drop(ring);
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wrap_matches_remainder() {
+        for len in 1..=17 {
+            for index in (0..100).chain((usize::MAX - 100)..=usize::MAX) {
+                assert_eq!(wrap(index, len), index % len, "{} % {}", index, len);
+            }
+        }
+    }
+}
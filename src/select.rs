@@ -0,0 +1,198 @@
+//! Blocking on several `RingQueue`s at once from a single thread.
+
+use crate::{
+    future::poll_select, Closed, DefaultRawMutex, PopError, PopTimeoutError, RingQueue, Storage,
+};
+
+use lock_api::RawMutex;
+
+use std::{
+    mem::MaybeUninit,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    // Wakes the current thread. Every wait on a thread shares it so that a queue which is waited on
+    // repeatedly only ever holds one waker for the thread.
+    static WAKER: Waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+}
+
+// Wakes a waiting thread by unparking it.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Pops values from whichever of several `RingQueue`s has one, blocking the current thread while
+/// they are all empty. Queues are checked in turn starting after the queue that the last value
+/// came from, so that a busy queue can't starve the rest. This is the blocking counterpart of
+/// `select_pop`.
+///
+/// A queue created with `Builder::spsc` only remembers one waiting task, so it must not be waited
+/// on by a `Select` and an async task at the same time.
+#[derive(Debug)]
+pub struct Select<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queues: Vec<&'a RingQueue<T, LEN, S, M>>,
+    // The index of the queue to check first on the next pop.
+    next: usize,
+}
+
+impl<'a, T, const LEN: usize, S, M> Select<'a, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Create a new `Select` which waits on no queues.
+    pub fn new() -> Self {
+        Self {
+            queues: vec![],
+            next: 0,
+        }
+    }
+
+    /// Adds `queue` to the queues to wait on. Returns the index which identifies values popped
+    /// from it.
+    pub fn add(&mut self, queue: &'a RingQueue<T, LEN, S, M>) -> usize {
+        self.queues.push(queue);
+        self.queues.len() - 1
+    }
+
+    /// The number of queues being waited on.
+    pub fn len(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Whether there are no queues being waited on.
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Gets the first value out of whichever queue has one first, along with the index of that
+    /// queue. Blocks while every queue is empty and at least one is open. Returns `Err(Closed)`
+    /// once every queue has been closed and emptied.
+    pub fn pop(&mut self) -> Result<(usize, T), Closed> {
+        WAKER.with(|waker| {
+            let mut cx = Context::from_waker(waker);
+            loop {
+                if let Poll::Ready(ret) = poll_select(&self.queues, &mut self.next, &mut cx) {
+                    return ret;
+                }
+                thread::park();
+            }
+        })
+    }
+
+    /// Gets the first value out of whichever queue has one, along with the index of that queue,
+    /// without blocking. Returns `PopError::Empty` if every queue is empty, or `PopError::Closed`
+    /// if they are also all closed.
+    pub fn try_pop(&mut self) -> Result<(usize, T), PopError> {
+        let len = self.queues.len();
+        let mut closed = 0;
+        for offset in 0..len {
+            let index = (self.next + offset) % len;
+            match self.queues[index].try_pop() {
+                Ok(value) => {
+                    self.next = (index + 1) % len;
+                    return Ok((index, value));
+                }
+                Err(PopError::Closed) => closed += 1,
+                Err(PopError::Empty) => {}
+            }
+        }
+        if closed == len {
+            Err(PopError::Closed)
+        } else {
+            Err(PopError::Empty)
+        }
+    }
+
+    /// Gets the first value out of whichever queue has one first, along with the index of that
+    /// queue. Blocks while every queue is empty and at least one is open, but for no longer than
+    /// `timeout`. Returns `PopTimeoutError::Timeout` if every queue was still empty when the
+    /// timeout elapsed, or `PopTimeoutError::Closed` if they were all closed and empty.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Result<(usize, T), PopTimeoutError> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.pop_until(deadline),
+            None => self.pop().map_err(|Closed| PopTimeoutError::Closed),
+        }
+    }
+
+    /// Gets the first value out of whichever queue has one first, along with the index of that
+    /// queue. Blocks while every queue is empty and at least one is open, but not past
+    /// `deadline`. Fails in the same ways as `pop_timeout`.
+    pub fn pop_until(&mut self, deadline: Instant) -> Result<(usize, T), PopTimeoutError> {
+        WAKER.with(|waker| {
+            let mut cx = Context::from_waker(waker);
+            loop {
+                if let Poll::Ready(ret) = poll_select(&self.queues, &mut self.next, &mut cx) {
+                    return ret.map_err(|Closed| PopTimeoutError::Closed);
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(PopTimeoutError::Timeout);
+                }
+                thread::park_timeout(deadline - now);
+            }
+        })
+    }
+}
+
+impl<T, const LEN: usize, S, M> Default for Select<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_waits_for_any_queue() {
+        let queues = [RingQueue::<u32, 2>::new(), RingQueue::new(), RingQueue::new()];
+        let mut select = Select::new();
+        for queue in &queues {
+            select.add(queue);
+        }
+        assert_eq!(select.try_pop(), Err(PopError::Empty));
+        assert_eq!(
+            select.pop_timeout(Duration::from_millis(10)),
+            Err(PopTimeoutError::Timeout)
+        );
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                queues[2].push(1).unwrap();
+            });
+            assert_eq!(select.pop(), Ok((2, 1)));
+        });
+        for queue in &queues {
+            queue.push(2).unwrap();
+        }
+        queues[0].push(3).unwrap();
+        // Every queue gets a turn before the first queue is popped from again.
+        assert_eq!(select.try_pop(), Ok((0, 2)));
+        assert_eq!(select.pop(), Ok((1, 2)));
+        assert_eq!(select.pop(), Ok((2, 2)));
+        assert_eq!(select.pop(), Ok((0, 3)));
+        for queue in &queues {
+            queue.close();
+        }
+        assert_eq!(select.pop(), Err(Closed));
+        assert_eq!(select.try_pop(), Err(PopError::Closed));
+    }
+}
@@ -0,0 +1,500 @@
+//! Access to the elements at either end of a `RingQueue` in place, for values which are too large
+//! to move around cheaply or which must stay queued until they have been handled.
+
+use crate::{
+    Closed, DefaultRawMutex, Inner, MutexGuard, PopError, RingQueue, Storage, TryPushError,
+};
+
+use lock_api::RawMutex;
+
+use std::{
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+
+/// The reserved element at the end of a `RingQueue`, which a value can be constructed in place
+/// in. Created by `RingQueue::reserve` and `RingQueue::try_reserve`.
+///
+/// The queue stays locked until the slot is committed or dropped, so the value should be
+/// constructed promptly. Dropping the slot without committing it leaves the queue unchanged and
+/// never drops anything written into the slot.
+#[derive(Debug)]
+#[must_use = "a reserved slot is only pushed into the queue when it is committed"]
+pub struct PushSlot<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+}
+
+impl<T, const LEN: usize, S, M> PushSlot<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// The memory which the value is to be constructed in.
+    pub fn as_uninit_mut(&mut self) -> &mut MaybeUninit<T> {
+        self.inner
+            .ring
+            .vacant_mut()
+            .expect("the queue had room when the slot was reserved")
+    }
+
+    /// A pointer to the memory which the value is to be constructed in.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.as_uninit_mut().as_mut_ptr()
+    }
+
+    /// Adds the value in the slot to the end of the queue.
+    ///
+    /// # Safety
+    ///
+    /// The slot must have been fully initialized, e.g. through `as_uninit_mut` or `as_mut_ptr`.
+    pub unsafe fn commit(mut self) {
+        self.inner.ring.push_vacant();
+        trace!("Pushed value into queue, {} queued", self.inner.len());
+        self.queue.notify_pushed(&mut self.inner, 1);
+    }
+}
+
+/// The first value in a `RingQueue`, which is only removed from the queue when the guard is
+/// committed. Created by `RingQueue::pop_guard` and `RingQueue::try_pop_guard`.
+///
+/// If the guard is dropped without being committed, including while unwinding from a panic, the
+/// value stays at the front of the queue to be popped again, so every value is processed at least
+/// once. The queue stays locked until the guard is committed or dropped.
+#[derive(Debug)]
+#[must_use = "the value is only removed from the queue when the guard is committed"]
+pub struct PopGuard<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    queue: &'a RingQueue<T, LEN, S, M>,
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+}
+
+impl<T, const LEN: usize, S, M> PopGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Removes the value from the queue.
+    pub fn commit(mut self) -> T {
+        let ret = self.inner.pop();
+        self.queue.notify_popped(&mut self.inner);
+        trace!("Popped value from queue, {} remaining", self.inner.len());
+        ret
+    }
+}
+
+impl<T, const LEN: usize, S, M> Deref for PopGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner
+            .front()
+            .expect("the queue had a value when the guard was created")
+    }
+}
+
+impl<T, const LEN: usize, S, M> DerefMut for PopGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.inner
+            .ring
+            .front_mut()
+            .expect("the queue had a value when the guard was created")
+    }
+}
+
+/// A view of every value in a `RingQueue`, which keeps the queue locked for as long as it lives.
+/// Created by `RingQueue::as_slices`.
+#[derive(Debug)]
+pub struct SlicesGuard<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+}
+
+impl<T, const LEN: usize, S, M> SlicesGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Gets the values in the queue, in order, as two slices. The second slice is only non-empty
+    /// when the values wrap around the end of the queue's storage.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.inner.ring.as_slices()
+    }
+
+    /// The number of values in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether the queue contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Every value in a `RingQueue` as one contiguous slice, which keeps the queue locked for as long
+/// as it lives. Created by `RingQueue::make_contiguous`.
+#[derive(Debug)]
+pub struct ContiguousGuard<'a, T, const LEN: usize, S = [MaybeUninit<T>; LEN], M = DefaultRawMutex>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    inner: MutexGuard<'a, M, Inner<T, S>>,
+}
+
+impl<T, const LEN: usize, S, M> Deref for ContiguousGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // `RingQueue::make_contiguous` made the values contiguous, and they can't have moved
+        // since because the queue has been locked ever since.
+        self.inner.ring.as_slices().0
+    }
+}
+
+impl<T, const LEN: usize, S, M> DerefMut for ContiguousGuard<'_, T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    fn deref_mut(&mut self) -> &mut [T] {
+        // Making values which are already contiguous contiguous again doesn't move them.
+        self.inner.ring.make_contiguous()
+    }
+}
+
+impl<T, const LEN: usize, S, M> RingQueue<T, LEN, S, M>
+where
+    S: Storage<T>,
+    M: RawMutex,
+{
+    /// Locks the queue and moves the values in it within its storage so that they form one
+    /// contiguous slice, keeping their order. The returned guard dereferences to that slice, e.g.
+    /// to hand it to code which needs a single buffer, and keeps the queue locked until it is
+    /// dropped. Runs in time linear in the capacity of the queue.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 4>::new();
+    /// for i in 0..4 {
+    ///     queue.push(i).unwrap();
+    /// }
+    /// queue.pop().unwrap();
+    /// queue.push(4).unwrap();
+    /// let mut values = queue.make_contiguous();
+    /// values[0] = 10;
+    /// assert_eq!(*values, [10, 2, 3, 4]);
+    /// drop(values);
+    /// assert_eq!(queue.pop(), Ok(10));
+    /// ```
+    pub fn make_contiguous(&self) -> ContiguousGuard<'_, T, LEN, S, M> {
+        let mut inner = self.inner.lock();
+        inner.ring.make_contiguous();
+        ContiguousGuard { inner }
+    }
+
+    /// Locks the queue and borrows the values in it, in order, without copying them. The queue
+    /// stays locked until the returned guard is dropped, so nothing can be pushed or popped while
+    /// it lives.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 4>::new();
+    /// for i in 0..4 {
+    ///     queue.push(i).unwrap();
+    /// }
+    /// queue.pop().unwrap();
+    /// queue.push(4).unwrap();
+    /// let contents = queue.as_slices();
+    /// assert_eq!(contents.as_slices(), (&[1, 2, 3][..], &[4][..]));
+    /// ```
+    pub fn as_slices(&self) -> SlicesGuard<'_, T, LEN, S, M> {
+        SlicesGuard {
+            inner: self.inner.lock(),
+        }
+    }
+
+    /// Gets a guard for the first value in the queue, which only removes the value from the queue
+    /// once it is committed. Blocks while the queue is empty and open. Returns `Err(Closed)` once
+    /// the queue has been closed and every value in it has been popped.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<u32, 2>::new();
+    /// queue.push(3).unwrap();
+    /// let guard = queue.pop_guard().unwrap();
+    /// assert_eq!(*guard, 3);
+    /// // The value is delivered again because the first guard wasn't committed.
+    /// drop(guard);
+    /// let guard = queue.pop_guard().unwrap();
+    /// assert_eq!(guard.commit(), 3);
+    /// assert!(queue.is_empty());
+    /// ```
+    pub fn pop_guard(&self) -> Result<PopGuard<'_, T, LEN, S, M>, Closed> {
+        span!("pop_guard");
+        let inner = self.lock_while(&self.pop_cond, |inner| inner.len() == 0 && !inner.closed);
+        if inner.len() == 0 {
+            return Err(Closed);
+        }
+        Ok(PopGuard { queue: self, inner })
+    }
+
+    /// Calls `f` on the first value in the queue, then removes the value from the queue and drops
+    /// it. Blocks while the queue is empty and open. Returns `Err(Closed)` without calling `f` once
+    /// the queue has been closed and every value in it has been popped. The queue stays locked
+    /// while `f` runs. If `f` panics, the value stays in the queue.
+    pub fn pop_with<F, R>(&self, f: F) -> Result<R, Closed>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = self.pop_guard()?;
+        let ret = f(&guard);
+        guard.commit();
+        Ok(ret)
+    }
+
+    /// Like `pop_with`, but gives `f` mutable access to the value, e.g. so that it can take parts
+    /// of the value out without cloning them.
+    pub fn pop_with_mut<F, R>(&self, f: F) -> Result<R, Closed>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.pop_guard()?;
+        let ret = f(&mut guard);
+        guard.commit();
+        Ok(ret)
+    }
+
+    /// Gets a guard for the first value in the queue if there is one. Returns `PopError::Empty`
+    /// immediately if the queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn try_pop_guard(&self) -> Result<PopGuard<'_, T, LEN, S, M>, PopError> {
+        span!("try_pop_guard");
+        let inner = self.inner.lock();
+        if inner.len() == 0 {
+            return Err(if inner.closed {
+                PopError::Closed
+            } else {
+                PopError::Empty
+            });
+        }
+        Ok(PopGuard { queue: self, inner })
+    }
+
+    /// Reserves the element at the end of the queue so that a value can be constructed in place in
+    /// it, instead of being constructed elsewhere and then moved into the queue. Blocks while the
+    /// queue is full, regardless of the queue's `FullPolicy`. Returns `Err(Closed)` if the queue is
+    /// closed.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<[u8; 4096], 2>::new();
+    /// let mut slot = queue.reserve().unwrap();
+    /// let ptr = slot.as_mut_ptr();
+    /// // SAFETY: Filling every byte of the array initializes it.
+    /// unsafe {
+    ///     ptr.cast::<u8>().write_bytes(7, 4096);
+    ///     slot.commit();
+    /// }
+    /// assert_eq!(queue.pop().unwrap()[4095], 7);
+    /// ```
+    pub fn reserve(&self) -> Result<PushSlot<'_, T, LEN, S, M>, Closed> {
+        span!("reserve");
+        let inner = self.lock_while(&self.push_cond, |inner| inner.is_full() && !inner.closed);
+        if inner.closed {
+            return Err(Closed);
+        }
+        Ok(PushSlot { queue: self, inner })
+    }
+
+    /// Adds a value constructed in place by `f` to the end of the queue. Blocks while the queue is
+    /// full, regardless of the queue's `FullPolicy`. `f` is given the element at the end of the
+    /// queue and must initialize it, returning the reference that `MaybeUninit::write` returns.
+    /// Returns `Err(Closed)` without calling `f` if the queue is closed. The queue stays locked
+    /// while `f` runs. If `f` panics, nothing is pushed.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    ///
+    /// let queue = RingQueue::<[u8; 4096], 2>::new();
+    /// queue.push_with(|slot| slot.write([7; 4096])).unwrap();
+    /// assert_eq!(queue.pop().unwrap()[4095], 7);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns a reference to anything other than the element that it was given.
+    pub fn push_with<F>(&self, f: F) -> Result<(), Closed>
+    where
+        F: FnOnce(&mut MaybeUninit<T>) -> &mut T,
+    {
+        let mut slot = self.reserve()?;
+        let uninit = slot.as_uninit_mut();
+        let ptr = uninit.as_mut_ptr();
+        // Getting a `&mut T` out of the element is how `f` proves that it initialized it.
+        assert!(ptr::eq(f(uninit), ptr), "`push_with` closure returned a different reference");
+        // SAFETY: This call is safe because `f` returned a reference to the element, which it
+        //         could only have gotten by initializing it.
+        unsafe { slot.commit() };
+        Ok(())
+    }
+
+    /// Moves the first value out of the queue directly into `out` if there is one, without
+    /// blocking. Returns whether a value was moved. Anything already in `out` is overwritten
+    /// without being dropped, so `out` can be reused as a buffer for many pops.
+    ///
+    /// ```
+    /// use ring_queue::RingQueue;
+    /// use std::mem::MaybeUninit;
+    ///
+    /// let queue = RingQueue::<[u8; 4096], 2>::new();
+    /// queue.push([7; 4096]).unwrap();
+    /// let mut out = MaybeUninit::uninit();
+    /// assert!(queue.pop_into(&mut out));
+    /// // SAFETY: `pop_into` returned `true`, so `out` is initialized.
+    /// assert_eq!(unsafe { out.assume_init_ref() }[4095], 7);
+    /// assert!(!queue.pop_into(&mut out));
+    /// ```
+    pub fn pop_into(&self, out: &mut MaybeUninit<T>) -> bool {
+        span!("pop_into");
+        let mut inner = self.inner.lock();
+        if !inner.ring.pop_into(out) {
+            return false;
+        }
+        self.notify_popped(&mut inner);
+        trace!("Popped value from queue, {} remaining", inner.len());
+        true
+    }
+
+    /// Reserves the element at the end of the queue if there is room for it. Returns
+    /// `TryPushError::Full` immediately if the queue is full, or `TryPushError::Closed` if it is
+    /// closed.
+    pub fn try_reserve(&self) -> Result<PushSlot<'_, T, LEN, S, M>, TryPushError<()>> {
+        span!("try_reserve");
+        let inner = self.inner.lock();
+        if inner.closed {
+            return Err(TryPushError::Closed(()));
+        }
+        if inner.is_full() {
+            return Err(TryPushError::Full(()));
+        }
+        Ok(PushSlot { queue: self, inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_slots_are_pushed() {
+        let queue = RingQueue::<String, 2>::new();
+        let mut slot = queue.reserve().unwrap();
+        slot.as_uninit_mut().write(String::from("first"));
+        // SAFETY: The slot was initialized just above.
+        unsafe { slot.commit() };
+        // A slot which is never committed leaves the queue unchanged.
+        drop(queue.try_reserve().unwrap());
+        assert_eq!(queue.len(), 1);
+        let mut slot = queue.try_reserve().unwrap();
+        slot.as_uninit_mut().write(String::from("second"));
+        // SAFETY: The slot was initialized just above.
+        unsafe { slot.commit() };
+        assert!(matches!(queue.try_reserve(), Err(TryPushError::Full(()))));
+        assert_eq!(queue.drain(), vec!["first", "second"]);
+        queue.close();
+        assert!(matches!(queue.reserve(), Err(Closed)));
+    }
+
+    #[test]
+    fn pop_into_moves_values_into_buffer() {
+        let queue = RingQueue::<String, 2>::new();
+        queue.push(String::from("first")).unwrap();
+        queue.push(String::from("second")).unwrap();
+        let mut out = MaybeUninit::uninit();
+        for expected in ["first", "second"] {
+            assert!(queue.pop_into(&mut out));
+            // SAFETY: `pop_into` returned `true`, so `out` is initialized, and nothing else will
+            //         read it before it is overwritten.
+            assert_eq!(unsafe { out.assume_init_read() }, expected);
+        }
+        assert!(!queue.pop_into(&mut out));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn uncommitted_pop_guards_leave_value_in_queue() {
+        let queue = RingQueue::<u32, 2>::new();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        let result = std::panic::catch_unwind(|| {
+            let mut guard = queue.pop_guard().unwrap();
+            *guard += 10;
+            panic!("handler crashed");
+        });
+        assert!(result.is_err());
+        assert_eq!(queue.try_pop_guard().unwrap().commit(), 11);
+        assert_eq!(queue.pop_guard().unwrap().commit(), 2);
+        assert!(matches!(queue.try_pop_guard(), Err(PopError::Empty)));
+        queue.close();
+        assert!(matches!(queue.pop_guard(), Err(Closed)));
+    }
+
+    #[test]
+    fn pop_with_removes_value_after_calling_f() {
+        let queue = RingQueue::<Vec<u32>, 2>::new();
+        queue.push(vec![1, 2, 3]).unwrap();
+        queue.push(vec![4, 5]).unwrap();
+        assert_eq!(queue.pop_with(Vec::len), Ok(3));
+        assert_eq!(queue.pop_with_mut(|values| values.pop()), Ok(Some(5)));
+        assert!(queue.is_empty());
+        queue.close();
+        assert_eq!(queue.pop_with(|_| unreachable!()), Err::<(), _>(Closed));
+    }
+
+    #[test]
+    fn push_with_constructs_values_in_place() {
+        let queue = RingQueue::<[u32; 64], 1>::new();
+        queue
+            .push_with(|slot| {
+                let ptr = slot.as_mut_ptr().cast::<u32>();
+                for i in 0..64 {
+                    // SAFETY: Every element of the array is written in bounds exactly once.
+                    unsafe { ptr.add(i).write(i as u32) };
+                }
+                // SAFETY: Every element of the array was written just above.
+                unsafe { slot.assume_init_mut() }
+            })
+            .unwrap();
+        assert_eq!(queue.pop().unwrap()[63], 63);
+        queue.close();
+        assert_eq!(queue.push_with(|_| unreachable!()), Err(Closed));
+    }
+}
@@ -0,0 +1,95 @@
+//! A spinlock for queues whose critical sections are too short to be worth a system call.
+
+use crate::RawMutexRingQueue;
+
+use core::{
+    hint,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use lock_api::{GuardSend, RawMutex};
+
+/// A `RawMutexRingQueue` protected by a `SpinRawMutex`. Best suited to small values, for which its
+/// critical sections are only a few instructions long.
+pub type SpinRingQueue<T, const LEN: usize> = RawMutexRingQueue<SpinRawMutex, T, LEN>;
+
+/// A mutex which waits by spinning instead of asking the operating system to put the thread to
+/// sleep. Neither locking nor unlocking it ever makes a system call, so a `SpinRingQueue`'s
+/// `try_push` and `try_pop` may be called from a signal handler as long as the handler cannot
+/// interrupt a thread that is in the middle of using the same queue.
+#[derive(Debug)]
+pub struct SpinRawMutex {
+    locked: AtomicBool,
+}
+
+// SAFETY: This impl is safe because `try_lock` only succeeds when it is the one to change `locked`
+//         from `false` to `true`, so at most one thread can hold the lock at a time, and the
+//         acquire and release orderings make everything done while holding the lock visible to
+//         the next thread to take it.
+unsafe impl RawMutex for SpinRawMutex {
+    const INIT: Self = Self {
+        locked: AtomicBool::new(false),
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock(&self) {
+        while !self.try_lock() {
+            // Only read the flag while it is set so that waiting threads don't fight over its
+            // cache line.
+            while self.is_locked() {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn spinlock_excludes_other_threads() {
+        let queue = Arc::new(SpinRingQueue::<u32, 8>::new());
+        let producers = (0..4)
+            .map(|n| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for i in (n * 1000)..(n * 1000 + 1000) {
+                        while queue.try_push(i).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut received = vec![];
+        while received.len() < 4000 {
+            match queue.try_pop() {
+                Some(value) => received.push(value),
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        received.sort_unstable();
+        assert_eq!(received, (0..4000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,322 @@
+//! A lock-free queue for exactly one producer and one consumer.
+
+use crate::padding::CachePadded;
+#[cfg(feature = "std")]
+use crate::sync::Mutex;
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+#[cfg(feature = "std")]
+use std::{
+    sync::atomic::AtomicBool,
+    thread::{self, Thread},
+};
+
+/// A fixed-size queue which can be split into exactly one `Producer` and one `Consumer`. Adding
+/// and removing values only touches two atomic counters, so neither side ever waits for the other.
+/// With the `std` feature, either side can also block by parking its thread, in which case the
+/// other side briefly locks a mutex to wake it.
+#[derive(Debug)]
+pub struct RingQueue<T, const LEN: usize> {
+    // INVARIANT: The `Self::distance(head, tail)` elements of `values` starting from
+    //            `Self::slot(head)`, wrapping around the end, are initialized. Only the `Consumer`
+    //            reads those elements and only the `Producer` writes the others.
+    values: [UnsafeCell<MaybeUninit<T>>; LEN],
+    // The number of values which have ever been popped, modulo `2 * LEN`. Only the `Consumer`
+    // changes it. Each counter has its own cache line so that the two sides don't invalidate each
+    // other's.
+    head: CachePadded<AtomicUsize>,
+    // The number of values which have ever been pushed, modulo `2 * LEN`. Only the `Producer`
+    // changes it.
+    //
+    // Letting the counters wrap around at `usize::MAX` instead would move them to a different
+    // element whenever `LEN` is not a power of two. Wrapping them at `2 * LEN` keeps them on the
+    // right element while still telling a full queue apart from an empty one.
+    tail: CachePadded<AtomicUsize>,
+    // The `Producer`'s thread while it is waiting for room.
+    #[cfg(feature = "std")]
+    producer: Parker,
+    // The `Consumer`'s thread while it is waiting for a value.
+    #[cfg(feature = "std")]
+    consumer: Parker,
+}
+
+impl<T, const LEN: usize> RingQueue<T, LEN> {
+    /// Create a new, empty `RingQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        Self::distance(head, self.tail.load(Ordering::Acquire))
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Splits the queue into its `Producer` and its `Consumer`, which can be sent to different
+    /// threads.
+    pub fn split(&mut self) -> (Producer<'_, T, LEN>, Consumer<'_, T, LEN>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    // The counter after `counter`.
+    const fn advance(counter: usize) -> usize {
+        if counter + 1 == 2 * LEN {
+            0
+        } else {
+            counter + 1
+        }
+    }
+
+    // The number of values between the counters `head` and `tail`.
+    const fn distance(head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * LEN - head
+        }
+    }
+
+    // The index in `values` of the element with the given counter.
+    const fn slot(counter: usize) -> usize {
+        if counter >= LEN {
+            counter - LEN
+        } else {
+            counter
+        }
+    }
+}
+
+impl<T, const LEN: usize> Default for RingQueue<T, LEN> {
+    fn default() -> Self {
+        Self {
+            values: [(); LEN].map(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            #[cfg(feature = "std")]
+            producer: Parker::default(),
+            #[cfg(feature = "std")]
+            consumer: Parker::default(),
+        }
+    }
+}
+
+impl<T, const LEN: usize> Drop for RingQueue<T, LEN> {
+    fn drop(&mut self) {
+        // Popping every remaining value drops exactly the elements which the invariant on
+        // `self.values` guarantees to be initialized.
+        let (_, mut consumer) = self.split();
+        while consumer.try_pop().is_some() {}
+    }
+}
+
+// SAFETY: This impl is safe because the only `Producer` and the only `Consumer` never access the
+//         same element of `values` at the same time, and every value that is moved into the queue
+//         by one thread may be moved out by another.
+unsafe impl<T, const LEN: usize> Sync for RingQueue<T, LEN> where T: Send {}
+
+/// The only handle which may add values to a split `RingQueue`.
+#[derive(Debug)]
+pub struct Producer<'a, T, const LEN: usize> {
+    queue: &'a RingQueue<T, LEN>,
+}
+
+impl<T, const LEN: usize> Producer<'_, T, LEN> {
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value
+    /// immediately if the queue is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        if RingQueue::<T, LEN>::distance(self.queue.head.load(Ordering::Acquire), tail) == LEN {
+            return Err(value);
+        }
+        // SAFETY: This write is safe because the element at `tail` is outside of `head..tail`, so
+        //         the `Consumer` will not read it until `tail` is advanced past it.
+        unsafe {
+            (*self.queue.values[RingQueue::<T, LEN>::slot(tail)].get()).write(value);
+        }
+        self.queue.tail.store(RingQueue::<T, LEN>::advance(tail), Ordering::SeqCst);
+        #[cfg(feature = "std")]
+        self.queue.consumer.unpark();
+        Ok(())
+    }
+
+    /// Adds a new value to the end of the queue, parking the thread while the queue is full.
+    #[cfg(feature = "std")]
+    pub fn push(&mut self, mut value: T) {
+        loop {
+            match self.try_push(value) {
+                Ok(()) => return,
+                Err(returned) => value = returned,
+            }
+            let queue = self.queue;
+            queue.producer.park_while(|| {
+                let tail = queue.tail.load(Ordering::Relaxed);
+                RingQueue::<T, LEN>::distance(queue.head.load(Ordering::SeqCst), tail) == LEN
+            });
+        }
+    }
+}
+
+/// The only handle which may remove values from a split `RingQueue`.
+#[derive(Debug)]
+pub struct Consumer<'a, T, const LEN: usize> {
+    queue: &'a RingQueue<T, LEN>,
+}
+
+impl<T, const LEN: usize> Consumer<'_, T, LEN> {
+    /// Gets the first value out of the queue if there is one. Returns `None` immediately if the
+    /// queue is empty.
+    pub fn try_pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: This read is safe because it is an invariant that the element at `head` is
+        //         initialized, and advancing `head` past it means it is never read again.
+        let slot = RingQueue::<T, LEN>::slot(head);
+        let value = unsafe { (*self.queue.values[slot].get()).assume_init_read() };
+        self.queue.head.store(RingQueue::<T, LEN>::advance(head), Ordering::SeqCst);
+        #[cfg(feature = "std")]
+        self.queue.producer.unpark();
+        Some(value)
+    }
+
+    /// Gets the first value out of the queue, parking the thread while the queue is empty.
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> T {
+        loop {
+            if let Some(value) = self.try_pop() {
+                return value;
+            }
+            let queue = self.queue;
+            queue.consumer.park_while(|| {
+                queue.head.load(Ordering::Relaxed) == queue.tail.load(Ordering::SeqCst)
+            });
+        }
+    }
+}
+
+// A slot for a thread which is parked until the other side of the queue makes progress.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct Parker {
+    // Whether `thread` may be parked. Checked on every operation, so that the mutex is only locked
+    // when a thread needs to be woken.
+    parked: AtomicBool,
+    thread: Mutex<Option<Thread>>,
+}
+
+#[cfg(feature = "std")]
+impl Parker {
+    // Parks the current thread if `condition` still holds once it has been registered.
+    fn park_while<F>(&self, condition: F)
+    where
+        F: FnOnce() -> bool,
+    {
+        *self.thread.lock() = Some(thread::current());
+        self.parked.store(true, Ordering::SeqCst);
+        // Checking the condition after setting `parked` means that the other side either sees
+        // `parked` or made progress that this check sees.
+        if condition() {
+            thread::park();
+        }
+        self.parked.store(false, Ordering::Relaxed);
+    }
+
+    // Wakes the registered thread if it may be parked. The caller must have made its progress
+    // visible with a `SeqCst` store first, so that either this sees `parked` or the parked thread
+    // sees that progress.
+    fn unpark(&self) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            if let Some(thread) = self.thread.lock().take() {
+                thread.unpark();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_operations_wrap_around() {
+        let mut queue = RingQueue::<u32, 2>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for i in 0..10 {
+            assert_eq!(producer.try_push(i), Ok(()));
+            assert_eq!(producer.try_push(i + 100), Ok(()));
+            assert_eq!(producer.try_push(i + 200), Err(i + 200));
+            assert_eq!(consumer.try_pop(), Some(i));
+            assert_eq!(consumer.try_pop(), Some(i + 100));
+            assert_eq!(consumer.try_pop(), None);
+        }
+    }
+
+    #[test]
+    fn counters_wrap_onto_the_right_slot() {
+        let mut queue = RingQueue::<u32, 3>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for i in 0..20 {
+            assert_eq!(producer.try_push(i), Ok(()));
+            assert_eq!(producer.try_push(i + 100), Ok(()));
+            assert_eq!(consumer.try_pop(), Some(i));
+            assert_eq!(consumer.try_pop(), Some(i + 100));
+        }
+        for i in 0..3 {
+            assert_eq!(producer.try_push(i), Ok(()));
+        }
+        assert_eq!(producer.try_push(3), Err(3));
+        assert_eq!(queue.len(), 3);
+        let mut empty = RingQueue::<u32, 0>::new();
+        let (mut producer, mut consumer) = empty.split();
+        assert_eq!(producer.try_push(0), Err(0));
+        assert_eq!(consumer.try_pop(), None);
+        assert_eq!(empty.len(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn blocking_halves_work_across_threads() {
+        let mut queue = RingQueue::<u32, 1>::new();
+        let (mut producer, mut consumer) = queue.split();
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..10_000 {
+                    producer.push(i);
+                }
+            });
+            for i in 0..10_000 {
+                assert_eq!(consumer.pop(), i);
+            }
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn drop_drops_remaining_values() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut queue = RingQueue::<Rc<()>, 3>::new();
+        let (mut producer, _) = queue.split();
+        for _ in 0..2 {
+            producer.try_push(Rc::clone(&value)).unwrap();
+        }
+        drop(queue);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+}
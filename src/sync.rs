@@ -0,0 +1,372 @@
+//! The mutexes and condition variables that every blocking queue is built on. They come from `std`
+//! unless the `parking_lot` feature is enabled, in which case they come from `parking_lot`.
+//! `RingQueue` can also be locked by any `lock_api::RawMutex` that the user supplies, and waits
+//! while holding one with a `RawCondvar`.
+//!
+//! Neither kind is ever poisoned. User code such as `Clone` and `Waker` implementations only runs
+//! while a queue is locked at points where the queue's state is consistent, so a thread that
+//! panicked while holding the lock can't have left anything half done. Letting the next thread
+//! carry on means that one panicking producer doesn't take down every consumer. That only covers
+//! the queues' own state, not values that user code panicked while changing, which is why a queue
+//! is only `UnwindSafe` when its values are.
+
+#[cfg(not(feature = "parking_lot"))]
+use core::hint;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync::{atomic::AtomicBool, PoisonError};
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use lock_api::{GuardNoSend, RawMutex};
+
+// How many times `StdRawMutex::lock` tries to take the lock before putting the thread to sleep.
+#[cfg(not(feature = "parking_lot"))]
+const STD_RAW_MUTEX_SPINS: u32 = 100;
+
+/// The `lock_api::RawMutex` that locks a `RingQueue` unless it is given another one. Like the
+/// other blocking queues' mutexes, it comes from `parking_lot` when the `parking_lot` feature is
+/// enabled and is otherwise built on `std`'s mutex and condition variable.
+pub struct DefaultRawMutex(
+    #[cfg(not(feature = "parking_lot"))] StdRawMutex,
+    #[cfg(feature = "parking_lot")] parking_lot::RawMutex,
+);
+
+// SAFETY: This impl is safe because it hands every call to a mutex which upholds the same
+//         contract.
+unsafe impl RawMutex for DefaultRawMutex {
+    #[cfg(not(feature = "parking_lot"))]
+    const INIT: Self = Self(StdRawMutex::new());
+    #[cfg(feature = "parking_lot")]
+    const INIT: Self = Self(parking_lot::RawMutex::INIT);
+
+    // Guards for `std`'s and `parking_lot`'s mutexes can't be sent to other threads either.
+    type GuardMarker = GuardNoSend;
+
+    fn lock(&self) {
+        self.0.lock();
+    }
+
+    fn try_lock(&self) -> bool {
+        self.0.try_lock()
+    }
+
+    unsafe fn unlock(&self) {
+        self.0.unlock();
+    }
+
+    fn is_locked(&self) -> bool {
+        self.0.is_locked()
+    }
+}
+
+impl Debug for DefaultRawMutex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DefaultRawMutex")
+            .field("locked", &self.is_locked())
+            .finish()
+    }
+}
+
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) type MutexGuard<'a, T> = std::sync::MutexGuard<'a, T>;
+#[cfg(feature = "parking_lot")]
+pub(crate) type MutexGuard<'a, T> = parking_lot::MutexGuard<'a, T>;
+
+// A mutual exclusion lock which can still be locked after a thread panicked while holding it.
+#[derive(Debug, Default)]
+pub(crate) struct Mutex<T>(
+    #[cfg(not(feature = "parking_lot"))] std::sync::Mutex<T>,
+    #[cfg(feature = "parking_lot")] parking_lot::Mutex<T>,
+);
+
+impl<T> Mutex<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        #[cfg(not(feature = "parking_lot"))]
+        return Self(std::sync::Mutex::new(value));
+        #[cfg(feature = "parking_lot")]
+        return Self(parking_lot::Mutex::new(value));
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(not(feature = "parking_lot"))]
+        return self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "parking_lot")]
+        return self.0.lock();
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        #[cfg(not(feature = "parking_lot"))]
+        return self.0.get_mut().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "parking_lot")]
+        return self.0.get_mut();
+    }
+}
+
+// A condition variable to wait on while holding a `Mutex`.
+#[derive(Debug, Default)]
+pub(crate) struct Condvar(
+    #[cfg(not(feature = "parking_lot"))] std::sync::Condvar,
+    #[cfg(feature = "parking_lot")] parking_lot::Condvar,
+);
+
+impl Condvar {
+    pub(crate) const fn new() -> Self {
+        #[cfg(not(feature = "parking_lot"))]
+        return Self(std::sync::Condvar::new());
+        #[cfg(feature = "parking_lot")]
+        return Self(parking_lot::Condvar::new());
+    }
+
+    // Releases `guard` until this condvar is notified, then locks it again. May wake spuriously.
+    #[allow(unused_mut)]
+    pub(crate) fn wait<'a, T>(&self, mut guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        #[cfg(not(feature = "parking_lot"))]
+        return self.0.wait(guard).unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "parking_lot")]
+        {
+            self.0.wait(&mut guard);
+            guard
+        }
+    }
+
+    // Waits for as long as `condition` holds.
+    #[allow(unused_mut)]
+    pub(crate) fn wait_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        #[cfg(not(feature = "parking_lot"))]
+        return self.0.wait_while(guard, condition).unwrap_or_else(PoisonError::into_inner);
+        #[cfg(feature = "parking_lot")]
+        {
+            self.0.wait_while(&mut guard, condition);
+            guard
+        }
+    }
+
+    // Waits for as long as `condition` holds, but for no longer than `timeout`. Also returns
+    // whether the timeout elapsed.
+    #[allow(unused_mut)]
+    pub(crate) fn wait_timeout_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+        condition: F,
+    ) -> (MutexGuard<'a, T>, bool)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        #[cfg(not(feature = "parking_lot"))]
+        {
+            let (guard, result) = self
+                .0
+                .wait_timeout_while(guard, timeout, condition)
+                .unwrap_or_else(PoisonError::into_inner);
+            (guard, result.timed_out())
+        }
+        #[cfg(feature = "parking_lot")]
+        {
+            let result = self.0.wait_while_for(&mut guard, condition, timeout);
+            (guard, result.timed_out())
+        }
+    }
+
+    // Like `wait`, but also wakes once `timeout` has elapsed.
+    #[allow(unused_mut)]
+    fn wait_timeout<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, T> {
+        #[cfg(not(feature = "parking_lot"))]
+        return self.0.wait_timeout(guard, timeout).unwrap_or_else(PoisonError::into_inner).0;
+        #[cfg(feature = "parking_lot")]
+        {
+            self.0.wait_for(&mut guard, timeout);
+            guard
+        }
+    }
+
+    pub(crate) fn notify_one(&self) {
+        self.0.notify_one();
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.0.notify_all();
+    }
+}
+
+// A mutex which can be unlocked without a guard, as `lock_api` needs. It is a flag which only
+// uses `std`'s mutex and condition variable to put threads to sleep while it is set.
+#[cfg(not(feature = "parking_lot"))]
+#[derive(Debug)]
+struct StdRawMutex {
+    // Whether the mutex is locked.
+    locked: AtomicBool,
+    // How many threads are asleep, or about to go to sleep, until `locked` is cleared.
+    sleepers: AtomicUsize,
+    // Held by a thread from before it counts itself in `sleepers` until it is asleep.
+    sleep: std::sync::Mutex<()>,
+    // Notified when `locked` is cleared while there are sleepers.
+    wake: std::sync::Condvar,
+}
+
+#[cfg(not(feature = "parking_lot"))]
+impl StdRawMutex {
+    const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            sleepers: AtomicUsize::new(0),
+            sleep: std::sync::Mutex::new(()),
+            wake: std::sync::Condvar::new(),
+        }
+    }
+
+    fn lock(&self) {
+        for _ in 0..STD_RAW_MUTEX_SPINS {
+            if self.try_lock() {
+                return;
+            }
+            hint::spin_loop();
+        }
+        let mut sleep = self.sleep.lock().unwrap_or_else(PoisonError::into_inner);
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        // `unlock` clears `locked` before checking for sleepers, so either this sees that `locked`
+        // was cleared or `unlock` sees this thread in `sleepers`. `unlock` has to lock `sleep`
+        // before notifying `wake`, so it can't do so until this thread is asleep.
+        while self
+            .locked
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            sleep = self.wake.wait(sleep).unwrap_or_else(PoisonError::into_inner);
+        }
+        self.sleepers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // Only the thread which changes `locked` from `false` to `true` holds the lock, and the
+    // acquire and release orderings make everything done while holding the lock visible to the
+    // next thread to take it.
+    fn try_lock(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            drop(self.sleep.lock().unwrap_or_else(PoisonError::into_inner));
+            self.wake.notify_one();
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+// A condition variable to wait on while holding a `lock_api` mutex, which `lock_api` doesn't
+// provide. Waiting threads sleep on a `Condvar` of its own and only unlock the caller's mutex once
+// they hold the `Mutex` that goes with it, which notifying threads have to lock first. A thread
+// that changes the condition while holding the caller's mutex therefore can't notify this before
+// the waiting threads are asleep.
+#[derive(Debug, Default)]
+pub(crate) struct RawCondvar {
+    // Held by a waiting thread from before it unlocks the caller's mutex until it is asleep.
+    lock: Mutex<()>,
+    condvar: Condvar,
+    // How many threads are waiting. Only increased while holding the caller's mutex, so a thread
+    // which changed the condition while holding it sees every thread that checked the condition
+    // before the change.
+    waiters: AtomicUsize,
+}
+
+impl RawCondvar {
+    pub(crate) const fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    // Waits for as long as `condition` holds.
+    pub(crate) fn wait_while<'a, R, T, F>(
+        &self,
+        mut guard: lock_api::MutexGuard<'a, R, T>,
+        mut condition: F,
+    ) -> lock_api::MutexGuard<'a, R, T>
+    where
+        R: RawMutex,
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut guard) {
+            self.park(&mut guard, None);
+        }
+        guard
+    }
+
+    // Waits for as long as `condition` holds, but for no longer than `timeout`. Also returns
+    // whether the timeout elapsed.
+    pub(crate) fn wait_timeout_while<'a, R, T, F>(
+        &self,
+        mut guard: lock_api::MutexGuard<'a, R, T>,
+        timeout: Duration,
+        mut condition: F,
+    ) -> (lock_api::MutexGuard<'a, R, T>, bool)
+    where
+        R: RawMutex,
+        F: FnMut(&mut T) -> bool,
+    {
+        let start = Instant::now();
+        while condition(&mut guard) {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return (guard, true);
+            }
+            self.park(&mut guard, Some(timeout - elapsed));
+        }
+        (guard, false)
+    }
+
+    pub(crate) fn notify_one(&self) {
+        if self.waiters.load(Ordering::Relaxed) > 0 {
+            drop(self.lock.lock());
+            self.condvar.notify_one();
+        }
+    }
+
+    pub(crate) fn notify_all(&self) {
+        if self.waiters.load(Ordering::Relaxed) > 0 {
+            drop(self.lock.lock());
+            self.condvar.notify_all();
+        }
+    }
+
+    // Releases `guard` until this condvar is notified or `timeout` elapses, then locks it again.
+    fn park<R, T>(&self, guard: &mut lock_api::MutexGuard<'_, R, T>, timeout: Option<Duration>)
+    where
+        R: RawMutex,
+    {
+        let lock = self.lock.lock();
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        lock_api::MutexGuard::unlocked(guard, || {
+            let lock = match timeout {
+                Some(timeout) => self.condvar.wait_timeout(lock, timeout),
+                None => self.condvar.wait(lock),
+            };
+            self.waiters.fetch_sub(1, Ordering::Relaxed);
+            // Waiting threads lock `lock` while holding the caller's mutex, so it has to be
+            // unlocked before the caller's mutex is locked again.
+            drop(lock);
+        });
+    }
+}
@@ -0,0 +1,269 @@
+//! A blocking queue whose producers and consumers lock different mutexes.
+
+use crate::{
+    padding::CachePadded,
+    ring::wrap,
+    sync::{Condvar, Mutex, MutexGuard},
+    Closed, PopError, PushError, TryPushError,
+};
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// A concurrent fixed-size queue with the same blocking behavior as `RingQueue`, but which
+/// protects its two ends with separate mutexes, as in Michael and Scott's two-lock queue. A
+/// producer and a consumer never wait for each other's lock, only for other producers or other
+/// consumers; `benches/throughput.rs` measures what that is worth against `RingQueue`. In exchange
+/// it has none of `RingQueue`'s batch, policy, or async operations, since those need to see both
+/// ends at once.
+#[derive(Debug)]
+pub struct TwoLockRingQueue<T, const LEN: usize> {
+    // INVARIANT: Starting at `*self.head` and wrapping around from the end of the queue to the
+    //            beginning, the first `self.len` elements of `self.values` are initialized. The
+    //            elements between the head and the tail are only accessed while holding the head
+    //            lock, and the others only while holding the tail lock.
+    values: [UnsafeCell<MaybeUninit<T>>; LEN],
+    // The index of the next value to pop. Consumers hold this lock.
+    head: CachePadded<Mutex<usize>>,
+    // The index of the next element to push into. Producers hold this lock.
+    tail: CachePadded<Mutex<usize>>,
+    // The number of values in the queue. It is only increased while holding the tail lock and only
+    // decreased while holding the head lock.
+    len: AtomicUsize,
+    // Whether any more values may be pushed into the queue.
+    closed: AtomicBool,
+    // The condition to wait on in `pop`. Only waited on while holding the head lock.
+    pop_cond: Condvar,
+    // The condition to wait on in `push`. Only waited on while holding the tail lock.
+    push_cond: Condvar,
+}
+
+impl<T, const LEN: usize> TwoLockRingQueue<T, LEN> {
+    /// Create a new `TwoLockRingQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of values currently in the queue.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Whether the queue currently contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of values that the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        LEN
+    }
+
+    /// Adds a new value to the end of the queue. Blocks while the queue is full and open. Returns
+    /// the value in `PushError::Closed` if the queue is closed.
+    pub fn push(&self, value: T) -> Result<(), PushError<T>> {
+        span!("push");
+        let tail = self
+            .push_cond
+            .wait_while(self.tail.lock(), |_| {
+                self.len.load(Ordering::Acquire) == LEN && !self.closed.load(Ordering::Acquire)
+            });
+        if self.closed.load(Ordering::Acquire) {
+            return Err(PushError::Closed(value));
+        }
+        self.push_locked(tail, value);
+        Ok(())
+    }
+
+    /// Adds a new value to the end of the queue if there is room for it. Returns the value
+    /// immediately in `TryPushError::Full` or `TryPushError::Closed` if the queue is full or
+    /// closed.
+    pub fn try_push(&self, value: T) -> Result<(), TryPushError<T>> {
+        span!("try_push");
+        let tail = self.tail.lock();
+        if self.closed.load(Ordering::Acquire) {
+            return Err(TryPushError::Closed(value));
+        }
+        if self.len.load(Ordering::Acquire) == LEN {
+            return Err(TryPushError::Full(value));
+        }
+        self.push_locked(tail, value);
+        Ok(())
+    }
+
+    /// Gets the first value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and every value in it has been popped.
+    pub fn pop(&self) -> Result<T, Closed> {
+        span!("pop");
+        let head = self
+            .pop_cond
+            .wait_while(self.head.lock(), |_| {
+                self.len.load(Ordering::Acquire) == 0 && !self.closed.load(Ordering::Acquire)
+            });
+        if self.len.load(Ordering::Acquire) == 0 {
+            return Err(Closed);
+        }
+        Ok(self.pop_locked(head))
+    }
+
+    /// Gets the first value out of the queue if there is one. Returns `PopError::Empty`
+    /// immediately if the queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        span!("try_pop");
+        let head = self.head.lock();
+        if self.len.load(Ordering::Acquire) == 0 {
+            return Err(if self.closed.load(Ordering::Acquire) {
+                PopError::Closed
+            } else {
+                PopError::Empty
+            });
+        }
+        Ok(self.pop_locked(head))
+    }
+
+    /// Closes the queue. Values that are already in the queue can still be popped, but every
+    /// attempt to push a new value will fail and every thread blocked in `push` or `pop` is woken.
+    pub fn close(&self) {
+        let _tail = self.tail.lock();
+        let _head = self.head.lock();
+        self.closed.store(true, Ordering::Release);
+        self.push_cond.notify_all();
+        self.pop_cond.notify_all();
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    // Pushes `value` into the queue, which must have room for it.
+    fn push_locked(&self, mut tail: MutexGuard<'_, usize>, value: T) {
+        // SAFETY: This write is safe because the queue is not full, so the element at the tail is
+        //         not between the head and the tail, and it is only accessed while holding the
+        //         tail lock.
+        unsafe {
+            (*self.values[*tail].get()).write(value);
+        }
+        *tail = wrap(*tail + 1, LEN);
+        let previous = self.len.fetch_add(1, Ordering::AcqRel);
+        trace!("Pushed value into queue, {} queued", previous + 1);
+        if previous + 1 < LEN {
+            // Let the next producer know that there is still room.
+            self.push_cond.notify_one();
+        }
+        drop(tail);
+        if previous == 0 {
+            // Consumers only wait while the queue is empty, so they only need to be woken when it
+            // stops being empty. Each consumer wakes the next while values remain.
+            let _head = self.head.lock();
+            self.pop_cond.notify_one();
+        }
+    }
+
+    // Pops the first value out of the queue, which must not be empty.
+    fn pop_locked(&self, mut head: MutexGuard<'_, usize>) -> T {
+        // SAFETY: This read is safe because it is an invariant that the element at the head is
+        //         initialized when the queue is not empty, and moving the head past it means it is
+        //         never read again.
+        let value = unsafe { (*self.values[*head].get()).assume_init_read() };
+        *head = wrap(*head + 1, LEN);
+        let previous = self.len.fetch_sub(1, Ordering::AcqRel);
+        trace!("Popped value from queue, {} remaining", previous - 1);
+        if previous > 1 {
+            // Let the next consumer know that there are still values.
+            self.pop_cond.notify_one();
+        }
+        drop(head);
+        if previous == LEN {
+            let _tail = self.tail.lock();
+            self.push_cond.notify_one();
+        }
+        value
+    }
+}
+
+impl<T, const LEN: usize> Default for TwoLockRingQueue<T, LEN> {
+    fn default() -> Self {
+        Self {
+            values: [(); LEN].map(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            head: CachePadded(Mutex::new(0)),
+            tail: CachePadded(Mutex::new(0)),
+            len: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            pop_cond: Condvar::new(),
+            push_cond: Condvar::new(),
+        }
+    }
+}
+
+impl<T, const LEN: usize> Drop for TwoLockRingQueue<T, LEN> {
+    fn drop(&mut self) {
+        // Popping every remaining value drops exactly the elements which the invariant on
+        // `self.values` guarantees to be initialized.
+        while self.try_pop().is_ok() {}
+    }
+}
+
+// SAFETY: This impl is safe because every access to an element of `values` happens while holding
+//         the lock for the end of the queue which that element belongs to, and every value that
+//         is moved into the queue by one thread may be moved out by another.
+unsafe impl<T, const LEN: usize> Sync for TwoLockRingQueue<T, LEN> where T: Send {}
+
+// A slot is only counted in `len` once its value has been written, so a panic can't leave the queue
+// in a state that later operations would misread. Neither lock is ever poisoned, though, so nothing
+// would warn about a value that a panic left half changed, and the queue is only unwind safe when
+// its values are.
+impl<T, const LEN: usize> UnwindSafe for TwoLockRingQueue<T, LEN> where T: UnwindSafe {}
+
+impl<T, const LEN: usize> RefUnwindSafe for TwoLockRingQueue<T, LEN> where T: RefUnwindSafe {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn producers_and_consumers_use_separate_locks() {
+        let queue = TwoLockRingQueue::<u32, 2>::new();
+        let mut received = thread::scope(|scope| {
+            for n in 0..2 {
+                let queue = &queue;
+                scope.spawn(move || {
+                    for i in (n * 1000)..(n * 1000 + 1000) {
+                        queue.push(i).unwrap();
+                    }
+                });
+            }
+            let consumers = (0..2)
+                .map(|_| {
+                    scope.spawn(|| (0..1000).map(|_| queue.pop().unwrap()).collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>();
+            consumers
+                .into_iter()
+                .flat_map(|consumer| consumer.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        received.sort_unstable();
+        assert_eq!(received, (0..2000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn close_wakes_blocked_threads() {
+        let queue = TwoLockRingQueue::<u32, 1>::new();
+        assert_eq!(queue.try_push(1), Ok(()));
+        thread::scope(|scope| {
+            let pusher = scope.spawn(|| queue.push(2));
+            queue.close();
+            assert_eq!(pusher.join().unwrap(), Err(PushError::Closed(2)));
+        });
+        assert_eq!(queue.pop(), Ok(1));
+        assert_eq!(queue.pop(), Err(Closed));
+        assert_eq!(queue.try_push(3), Err(TryPushError::Closed(3)));
+    }
+}
@@ -0,0 +1,133 @@
+//! A single-slot queue which only ever holds the most recent value.
+
+use crate::{Closed, OverwritingRingQueue, PopError, PopTimeoutError};
+
+use std::time::{Duration, Instant};
+
+/// A concurrent queue which holds at most one value. Pushing replaces the value which has yet to
+/// be popped, if there is one, so consumers only ever see the latest value. Suited to
+/// distributing state, such as a sensor's latest reading, where intermediate values don't matter.
+#[derive(Debug)]
+pub struct WatchQueue<T> {
+    queue: OverwritingRingQueue<T, 1>,
+}
+
+impl<T> WatchQueue<T> {
+    /// Create a new, empty `WatchQueue`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether there is a value which has yet to be popped.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Gets the latest value out of the queue. Blocks while the queue is empty and open. Returns
+    /// `Err(Closed)` once the queue has been closed and its last value has been popped.
+    pub fn pop(&self) -> Result<T, Closed> {
+        self.queue.pop()
+    }
+
+    /// Gets the latest value out of the queue if there is one. Returns `PopError::Empty`
+    /// immediately if the queue is empty, or `PopError::Closed` if it is also closed.
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.queue.try_pop()
+    }
+
+    /// Gets the latest value out of the queue. Blocks while the queue is empty and open, but for
+    /// no longer than `timeout`. Returns `PopTimeoutError::Timeout` if the queue was still empty
+    /// when the timeout elapsed, or `PopTimeoutError::Closed` if the queue was closed and empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.queue.pop_timeout(timeout)
+    }
+
+    /// Gets the latest value out of the queue. Blocks while the queue is empty and open, but not
+    /// past `deadline`. Fails in the same ways as `pop_timeout`.
+    pub fn pop_until(&self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.queue.pop_until(deadline)
+    }
+
+    /// Replaces the value in the queue with `value`. Returns the value which did not make it into
+    /// the queue: the replaced value if there was one, or `value` itself if the queue is closed.
+    pub fn push(&self, value: T) -> Option<T> {
+        self.queue.push(value)
+    }
+
+    /// Gets a copy of the latest value without removing it. Returns `None` if the queue is empty.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.queue.peek()
+    }
+
+    /// Closes the queue. The latest value can still be popped, but every attempt to push a new
+    /// value will fail and every thread blocked in `pop` is woken.
+    pub fn close(&self) {
+        self.queue.close()
+    }
+
+    /// Whether `close` has been called on this queue.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+impl<T> Clone for WatchQueue<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T> defmt::Format for WatchQueue<T> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "WatchQueue {{ queue: {} }}", self.queue);
+    }
+}
+
+impl<T> Default for WatchQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn push_replaces_pending_value() {
+        let queue = WatchQueue::new();
+        assert_eq!(queue.push(1), None);
+        assert_eq!(queue.push(2), Some(1));
+        assert_eq!(queue.peek(), Some(2));
+        assert_eq!(queue.pop(), Ok(2));
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 3..10 {
+                    queue.push(i);
+                }
+                queue.close();
+            });
+            let mut latest = 0;
+            while let Ok(value) = queue.pop() {
+                assert!(value > latest);
+                latest = value;
+            }
+            assert_eq!(latest, 9);
+        });
+        assert_eq!(queue.push(10), Some(10));
+    }
+}
@@ -0,0 +1,57 @@
+//! Checks that `CriticalSectionRingQueue` can be used the way an interrupt handler would use it.
+
+#![cfg(feature = "critical-section")]
+
+use ring_queue::CriticalSectionRingQueue;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+// Counts the allocations made by the current thread so that other test threads don't interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+// Shared between the main loop and the interrupt handler the way firmware would share it.
+static QUEUE: CriticalSectionRingQueue<u8, 16> = CriticalSectionRingQueue::new();
+
+#[test]
+fn push_from_interrupt_does_not_allocate() {
+    let before = allocations();
+    // An interrupt handler runs with interrupts disabled, which is itself a critical section.
+    critical_section::with(|_| {
+        for byte in 0..20 {
+            let _ = QUEUE.try_push(byte);
+        }
+    });
+    let after = allocations();
+    assert_eq!(after, before);
+    assert!(QUEUE.is_full());
+    assert_eq!(QUEUE.try_push(20), Err(20));
+    for byte in 0..16 {
+        assert_eq!(QUEUE.try_pop(), Some(byte));
+    }
+    assert_eq!(QUEUE.try_pop(), None);
+}
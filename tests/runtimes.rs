@@ -0,0 +1,77 @@
+//! Runs the async API under executors other than tokio to make sure that it only relies on the
+//! `Waker` contract and not on any one runtime's behavior.
+
+#![cfg(feature = "std")]
+
+use ring_queue::{select_pop, RingQueue};
+
+use std::{future::Future, pin::Pin};
+
+const VALUES: u32 = 1000;
+
+// Moves `VALUES` values through a one-slot queue between two tasks so that both sides have to
+// wait on each other for nearly every value.
+async fn ping_pong<S>(spawn: S)
+where
+    S: Fn(Pin<Box<dyn Future<Output = ()> + Send>>),
+{
+    let (sender, receiver) = RingQueue::<u32, 1>::new().split();
+    spawn(Box::pin(async move {
+        for i in 0..VALUES {
+            sender.push_async(i).await.unwrap();
+        }
+    }));
+    let mut expected = 0;
+    while let Ok(value) = receiver.pop_async().await {
+        assert_eq!(value, expected);
+        expected += 1;
+    }
+    assert_eq!(expected, VALUES);
+}
+
+#[test]
+fn async_std_ping_pong() {
+    async_std::task::block_on(ping_pong(|task| {
+        async_std::task::spawn(task);
+    }));
+}
+
+#[test]
+fn smol_ping_pong() {
+    smol::block_on(ping_pong(|task| smol::spawn(task).detach()));
+}
+
+#[test]
+fn async_std_select_pop() {
+    async_std::task::block_on(async {
+        let first = RingQueue::<u32, 1>::new();
+        let second = RingQueue::<u32, 1>::new();
+        let (_, value) = smol::future::zip(
+            async {
+                async_std::task::yield_now().await;
+                second.push_async(7).await.unwrap();
+            },
+            select_pop(&[&first, &second]),
+        )
+        .await;
+        assert_eq!(value, Ok((1, 7)));
+    });
+}
+
+#[test]
+fn smol_pop_batch_async() {
+    smol::block_on(async {
+        let queue = RingQueue::<u32, 4>::new();
+        let (_, values) = smol::future::zip(
+            async {
+                smol::future::yield_now().await;
+                for i in 0..4 {
+                    queue.push_async(i).await.unwrap();
+                }
+            },
+            queue.pop_batch_async(3),
+        )
+        .await;
+        assert_eq!(values, Ok(vec![0, 1, 2]));
+    });
+}